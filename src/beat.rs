@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+// A detected onset, `strength` is 0..1 with a flux right at the threshold giving 0.5.
+#[derive(Debug, Clone, Copy)]
+pub struct Beat {
+    pub strength: f32,
+}
+
+// Spectral flux onset detector. The flux is the summed increase in log magnitude across
+// `bins` since the previous frame, and a beat fires when it rises above `sensitivity` times
+// its running mean over the last `window` seconds.
+pub struct OnsetDetector {
+    bins: Range<usize>,
+    previous: Vec<f32>,
+    history: VecDeque<f32>,
+    history_len: usize,
+    sensitivity: f32,
+    // Frames left before another beat may fire, so one hit doesn't trigger twice.
+    refractory: usize,
+    min_interval: usize,
+    flux: f32,
+}
+
+impl OnsetDetector {
+    pub fn new(bins: Range<usize>, frame_rate: f32, window: f32, sensitivity: f32) -> Self {
+        let history_len = usize::max((window * frame_rate) as usize, 1);
+        OnsetDetector {
+            previous: vec![0.0; bins.len()],
+            bins,
+            history: VecDeque::with_capacity(history_len),
+            history_len,
+            sensitivity,
+            refractory: 0,
+            // Nothing much faster than 1/16th notes at 180 BPM.
+            min_interval: (0.08 * frame_rate) as usize,
+            flux: 0.0,
+        }
+    }
+
+    // Flux of the last frame passed to `detect`, this is the onset envelope.
+    pub fn flux(&self) -> f32 {
+        self.flux
+    }
+
+    pub fn detect(&mut self, spectrum: &[f32]) -> Option<Beat> {
+        let mut flux = 0.0;
+        for (&x, prev) in spectrum[self.bins.clone()].iter().zip(self.previous.iter_mut()) {
+            // Log compression keeps quiet onsets from being drowned by loud sustained notes.
+            let x = (1.0 + 1000.0 * x).ln();
+            flux += f32::max(x - *prev, 0.0);
+            *prev = x;
+        }
+        self.flux = flux;
+
+        let mean = if self.history.is_empty() {
+            flux
+        } else {
+            self.history.iter().sum::<f32>() / self.history.len() as f32
+        };
+
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(flux);
+
+        let threshold = mean * self.sensitivity;
+        if self.refractory > 0 {
+            self.refractory -= 1;
+            None
+        } else if flux > threshold && threshold > 0.0 {
+            self.refractory = self.min_interval;
+            Some(Beat {
+                strength: f32::min(flux / (2.0 * threshold), 1.0),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+// Turns discrete beats into a flash that decays over `decay` seconds.
+pub struct BeatFlash {
+    level: f32,
+    decay_rate: f32,
+}
+
+impl BeatFlash {
+    pub fn new(frame_rate: f32, decay: f32) -> Self {
+        BeatFlash {
+            level: 0.0,
+            decay_rate: if decay > 0.0 { (1.0 / (-decay * frame_rate)).exp() } else { 0.0 },
+        }
+    }
+
+    pub fn update(&mut self, beat: Option<Beat>) -> f32 {
+        self.level *= self.decay_rate;
+        if let Some(beat) = beat {
+            self.level = f32::max(self.level, beat.strength);
+        }
+        self.level
+    }
+}
+
+// Current tempo estimate, `phase` runs 0..1 through each beat with 0 on the beat.
+#[derive(Debug, Clone, Copy)]
+pub struct Tempo {
+    pub bpm: f32,
+    pub phase: f32,
+}
+
+// Tempo tracker working off the onset envelope. The beat period is the lag with the
+// strongest autocorrelation between 60 and 180 BPM, and the phase is the offset into that
+// period where past onsets line up best. Between estimates the phase free runs, so it keeps
+// ticking through quiet passages.
+pub struct TempoTracker {
+    envelope: VecDeque<f32>,
+    envelope_len: usize,
+    frame_rate: f32,
+    min_lag: usize,
+    max_lag: usize,
+    // Beat period in frames.
+    period: f32,
+    phase: f32,
+    confidence: f32,
+    frames_since_estimate: usize,
+}
+
+impl TempoTracker {
+    pub fn new(frame_rate: f32, window: f32) -> Self {
+        let envelope_len = (window * frame_rate) as usize;
+        TempoTracker {
+            envelope: VecDeque::with_capacity(envelope_len),
+            envelope_len,
+            frame_rate,
+            min_lag: usize::max((frame_rate * 60.0 / 180.0) as usize, 1),
+            max_lag: (frame_rate * 60.0 / 60.0).ceil() as usize,
+            period: frame_rate / 2.0,
+            phase: 0.0,
+            confidence: 0.0,
+            frames_since_estimate: 0,
+        }
+    }
+
+    // Feeds one frame of onset envelope, returns the tempo once there's a confident estimate.
+    pub fn update(&mut self, flux: f32) -> Option<Tempo> {
+        if self.envelope.len() == self.envelope_len {
+            self.envelope.pop_front();
+        }
+        self.envelope.push_back(flux);
+
+        self.phase = (self.phase + self.period.recip()).fract();
+
+        // Re-estimating every frame is wasted effort, a few times a second is plenty.
+        self.frames_since_estimate += 1;
+        if self.frames_since_estimate >= 8 && self.envelope.len() > 2 * self.max_lag {
+            self.frames_since_estimate = 0;
+            self.estimate();
+        }
+
+        if self.confidence > 0.1 {
+            Some(Tempo {
+                bpm: 60.0 * self.frame_rate / self.period,
+                phase: self.phase,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn estimate(&mut self) {
+        let mean = self.envelope.iter().sum::<f32>() / self.envelope.len() as f32;
+        let env: Vec<f32> = self.envelope.iter().map(|x| x - mean).collect();
+        let energy: f32 = env.iter().map(|x| x * x).sum();
+        if energy <= 0.0 {
+            self.confidence = 0.0;
+            return;
+        }
+
+        let acf = |lag: usize| env.iter().zip(env[lag..].iter()).map(|(a, b)| a * b).sum::<f32>() / energy;
+
+        // Lean towards 120 BPM so the tracker doesn't flip between octaves of the same tempo.
+        let weighted = |lag: usize| {
+            let bpm = 60.0 * self.frame_rate / lag as f32;
+            let octaves = (bpm / 120.0).log2();
+            acf(lag) * (-0.5 * octaves * octaves).exp()
+        };
+
+        let best = (self.min_lag..=self.max_lag)
+            .max_by(|&a, &b| weighted(a).partial_cmp(&weighted(b)).unwrap())
+            .unwrap();
+        self.confidence = acf(best);
+
+        // Parabolic interpolation around the peak for a fractional period.
+        let (y0, y1, y2) = (acf(best - 1), acf(best), acf(best + 1));
+        let denom = y0 - 2.0 * y1 + y2;
+        let offset = if denom.abs() > 1e-6 { f32::min(0.5 * (y0 - y2) / denom, 0.5).max(-0.5) } else { 0.0 };
+        self.period = best as f32 + offset;
+
+        // Find how many frames ago the last beat was by summing the envelope along a comb. The
+        // teeth follow the fractional period so they don't drift off the beats further back.
+        let n = env.len();
+        let frames_ago = (0..best).max_by(|&a, &b| {
+            let comb = |o: usize| (0..).map(|k| o + (k as f32 * self.period).round() as usize).take_while(|&i| i < n).map(|i| env[n - 1 - i]).sum::<f32>();
+            comb(a).partial_cmp(&comb(b)).unwrap()
+        }).unwrap();
+        self.phase = (frames_ago as f32 / self.period).fract();
+    }
+}
@@ -13,7 +13,6 @@ impl RGB {
         RGB { r, g, b }
     }
 
-
     /// Views struct as a slice of bytes, for convient allocationless processing.
     /// The safetly of this method is tied to the fact that the struct is `#[repr(C)]`.
     pub fn as_slice<'a>(&'a self) -> &'a [u8] {
@@ -22,42 +21,155 @@ impl RGB {
         }
     }
 
-    pub fn from_hex(hex: &str) -> Self {
-        let tmp = u32::from_str_radix(hex, 16).unwrap();
-        let r: u8 = ((tmp >> 16) & 0xFF) as u8;
-        let g: u8 = ((tmp >> 8) & 0xFF) as u8;
-        let b: u8 = (tmp & 0xFF) as u8;
-
-        RGB {
-            r, g, b
-        }
+    pub fn from_u32(c: u32) -> Self {
+        RGB::new((c >> 16) as u8, (c >> 8) as u8, c as u8)
     }
 
     pub fn saturate(self) -> RGB {
-        let max = self.as_slice().iter().max().unwrap();
-        let ratio = 255.0 / *max as f32;
+        let max = *self.as_slice().iter().max().unwrap();
+        if max == 0 {
+            // Black has no hue to saturate.
+            return self;
+        }
+        let ratio = 255.0 / max as f32;
         ratio * self
     }
+}
 
-    pub fn super_hdr(rhs: f32) -> Self {
-        let q = ((rhs * 765.0) as u32).min(765);
-        RGB {
-            r: q.saturating_sub(512) as u8,
-            g: q.saturating_sub(256).min(511) as u8,
-            b: q.min(255) as u8
+// Parses `#RRGGBB`, `RRGGBB`, the `#RGB` shorthand, CSS named colors, and the functional forms
+// `rgb(r, g, b)` with channels 0..255, `hsv(h, s, v)` and `hsl(h, s, l)` with hue in degrees.
+// Any channel but hue may also be a percentage.
+impl std::str::FromStr for RGB {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let lower = s.to_ascii_lowercase();
+
+        if let Some(&(_, c)) = NAMED_COLORS.iter().find(|&&(name, _)| name == lower) {
+            return Ok(RGB::from_u32(c));
+        }
+
+        if let Some(open) = lower.find('(') {
+            if !lower.ends_with(')') {
+                return Err(format!("color `{}` is missing its closing `)`", s));
+            }
+            let args: Vec<&str> = lower[open + 1..lower.len() - 1].split(',').map(str::trim).collect();
+            if args.len() != 3 {
+                return Err(format!("color `{}` should have 3 components, found {}", s, args.len()));
+            }
+
+            // Channel `idx` scaled to 0..1 from a percentage or from 0..`max`.
+            let channel = |idx: usize, max: f32| -> Result<f32, String> {
+                let arg = args[idx];
+                let (value, scale) = match arg.strip_suffix('%') {
+                    Some(pct) => (pct.trim(), 100.0),
+                    None => (arg, max),
+                };
+                let value: f32 = value.parse().map_err(|e| format!("bad component `{}` in color `{}`: {}", arg, s, e))?;
+                if value < 0.0 || value > scale {
+                    return Err(format!("component `{}` in color `{}` is out of range", arg, s));
+                }
+                Ok(value / scale)
+            };
+            let hue = || args[0].trim_end_matches("deg").trim().parse::<f32>()
+                .map(|h| h.rem_euclid(360.0))
+                .map_err(|e| format!("bad hue `{}` in color `{}`: {}", args[0], s, e));
+
+            return match lower[..open].trim() {
+                "rgb" => Ok(RGB::new(
+                    (channel(0, 255.0)? * 255.0).round() as u8,
+                    (channel(1, 255.0)? * 255.0).round() as u8,
+                    (channel(2, 255.0)? * 255.0).round() as u8,
+                )),
+                "hsv" => Ok(Hsv { h: hue()?, s: channel(1, 1.0)?, v: channel(2, 1.0)? }.into()),
+                "hsl" => Ok(Hsl { h: hue()?, s: channel(1, 1.0)?, l: channel(2, 1.0)? }.into()),
+                f => Err(format!("unknown color function `{}`, expected one of: rgb, hsv, hsl", f)),
+            };
+        }
+
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("`{}` is not a color name or hex color", s));
+        }
+        match hex.len() {
+            6 => Ok(RGB::from_u32(u32::from_str_radix(hex, 16).unwrap())),
+            // Each digit is doubled up, so `F0A` is `FF00AA`.
+            3 => {
+                let c = u32::from_str_radix(hex, 16).unwrap();
+                Ok(RGB::new(
+                    ((c >> 8) & 0xF) as u8 * 17,
+                    ((c >> 4) & 0xF) as u8 * 17,
+                    (c & 0xF) as u8 * 17,
+                ))
+            }
+            n => Err(format!("hex color `{}` should have 3 or 6 digits, found {}", s, n)),
         }
     }
+}
 
-    pub fn hdr(rhs: f32) -> Self {
-        let q = ((rhs * 511.0) as u32).min(511);
-        RGB {
-            r: 0,
-            g: q.saturating_sub(256) as u8,
-            b: q.min(255) as u8
+// Splits a comma separated list of colors, leaving the commas inside `rgb(...)` and friends alone.
+pub fn split_list(s: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                fields.push(&s[start..idx]);
+                start = idx + 1;
+            }
+            _ => (),
         }
     }
+    fields.push(&s[start..]);
+    fields
 }
 
+// CSS named colors.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xF0F8FF), ("antiquewhite", 0xFAEBD7), ("aqua", 0x00FFFF), ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF), ("beige", 0xF5F5DC), ("bisque", 0xFFE4C4), ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD), ("blue", 0x0000FF), ("blueviolet", 0x8A2BE2), ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887), ("cadetblue", 0x5F9EA0), ("chartreuse", 0x7FFF00), ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50), ("cornflowerblue", 0x6495ED), ("cornsilk", 0xFFF8DC), ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF), ("darkblue", 0x00008B), ("darkcyan", 0x008B8B), ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9), ("darkgreen", 0x006400), ("darkgrey", 0xA9A9A9), ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B), ("darkolivegreen", 0x556B2F), ("darkorange", 0xFF8C00),
+    ("darkorchid", 0x9932CC), ("darkred", 0x8B0000), ("darksalmon", 0xE9967A), ("darkseagreen", 0x8FBC8F),
+    ("darkslateblue", 0x483D8B), ("darkslategray", 0x2F4F4F), ("darkslategrey", 0x2F4F4F),
+    ("darkturquoise", 0x00CED1), ("darkviolet", 0x9400D3), ("deeppink", 0xFF1493), ("deepskyblue", 0x00BFFF),
+    ("dimgray", 0x696969), ("dimgrey", 0x696969), ("dodgerblue", 0x1E90FF), ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0), ("forestgreen", 0x228B22), ("fuchsia", 0xFF00FF), ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF), ("gold", 0xFFD700), ("goldenrod", 0xDAA520), ("gray", 0x808080),
+    ("green", 0x008000), ("greenyellow", 0xADFF2F), ("grey", 0x808080), ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4), ("indianred", 0xCD5C5C), ("indigo", 0x4B0082), ("ivory", 0xFFFFF0),
+    ("khaki", 0xF0E68C), ("lavender", 0xE6E6FA), ("lavenderblush", 0xFFF0F5), ("lawngreen", 0x7CFC00),
+    ("lemonchiffon", 0xFFFACD), ("lightblue", 0xADD8E6), ("lightcoral", 0xF08080), ("lightcyan", 0xE0FFFF),
+    ("lightgoldenrodyellow", 0xFAFAD2), ("lightgray", 0xD3D3D3), ("lightgreen", 0x90EE90),
+    ("lightgrey", 0xD3D3D3), ("lightpink", 0xFFB6C1), ("lightsalmon", 0xFFA07A), ("lightseagreen", 0x20B2AA),
+    ("lightskyblue", 0x87CEFA), ("lightslategray", 0x778899), ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE), ("lightyellow", 0xFFFFE0), ("lime", 0x00FF00), ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6), ("magenta", 0xFF00FF), ("maroon", 0x800000), ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD), ("mediumorchid", 0xBA55D3), ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371), ("mediumslateblue", 0x7B68EE), ("mediumspringgreen", 0x00FA9A),
+    ("mediumturquoise", 0x48D1CC), ("mediumvioletred", 0xC71585), ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA), ("mistyrose", 0xFFE4E1), ("moccasin", 0xFFE4B5), ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080), ("oldlace", 0xFDF5E6), ("olive", 0x808000), ("olivedrab", 0x6B8E23),
+    ("orange", 0xFFA500), ("orangered", 0xFF4500), ("orchid", 0xDA70D6), ("palegoldenrod", 0xEEE8AA),
+    ("palegreen", 0x98FB98), ("paleturquoise", 0xAFEEEE), ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5), ("peachpuff", 0xFFDAB9), ("peru", 0xCD853F), ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD), ("powderblue", 0xB0E0E6), ("purple", 0x800080), ("rebeccapurple", 0x663399),
+    ("red", 0xFF0000), ("rosybrown", 0xBC8F8F), ("royalblue", 0x4169E1), ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072), ("sandybrown", 0xF4A460), ("seagreen", 0x2E8B57), ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D), ("silver", 0xC0C0C0), ("skyblue", 0x87CEEB), ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090), ("slategrey", 0x708090), ("snow", 0xFFFAFA), ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4), ("tan", 0xD2B48C), ("teal", 0x008080), ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347), ("turquoise", 0x40E0D0), ("violet", 0xEE82EE), ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF), ("whitesmoke", 0xF5F5F5), ("yellow", 0xFFFF00), ("yellowgreen", 0x9ACD32),
+];
+
 impl Mul<RGB> for f32 {
     type Output = RGB;
     fn mul(self, rhs: RGB) -> Self::Output {
@@ -74,7 +186,7 @@ impl Mul<RGB> for RGB {
     fn mul(self, rhs: RGB) -> Self::Output {
         let r = (self.r as u16 * rhs.r as u16) / 255;
         let g = (self.g as u16 * rhs.g as u16) / 255;
-        let b = (self.g as u16 * rhs.b as u16) / 255;
+        let b = (self.b as u16 * rhs.b as u16) / 255;
 
         RGB
         {
@@ -83,4 +195,480 @@ impl Mul<RGB> for RGB {
             b: b as u8
         }
     }
+}
+
+/// High precision color on the same 0..255 scale as `RGB`, so fractional levels survive
+/// until the frame is quantized for the wire.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct RGBf {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl From<RGB> for RGBf {
+    fn from(c: RGB) -> Self {
+        RGBf {
+            r: c.r as f32,
+            g: c.g as f32,
+            b: c.b as f32,
+        }
+    }
+}
+
+impl Mul<RGBf> for f32 {
+    type Output = RGBf;
+    fn mul(self, rhs: RGBf) -> Self::Output {
+        RGBf {
+            r: (rhs.r * self).min(255.0),
+            g: (rhs.g * self).min(255.0),
+            b: (rhs.b * self).min(255.0),
+        }
+    }
+}
+
+impl From<RGBf> for RGB {
+    fn from(c: RGBf) -> Self {
+        let q = |v: f32| f32::min(v.round(), 255.0).max(0.0) as u8;
+        RGB::new(q(c.r), q(c.g), q(c.b))
+    }
+}
+
+impl RGBf {
+    // Composites `top` onto `self` with `mode`, `opacity` fading between `self` alone (0) and
+    // the full effect (1).
+    pub fn blend(self, top: RGBf, mode: BlendMode, opacity: f32) -> RGBf {
+        let mix = |below: f32, above: f32| {
+            let blended = match mode {
+                BlendMode::Over => above,
+                BlendMode::Multiply => below * above / 255.0,
+                BlendMode::Screen => 255.0 - (255.0 - below) * (255.0 - above) / 255.0,
+                BlendMode::Add => f32::min(below + above, 255.0),
+                BlendMode::Max => below.max(above),
+            };
+            below + (blended - below) * opacity
+        };
+
+        RGBf {
+            r: mix(self.r, top.r),
+            g: mix(self.g, top.g),
+            b: mix(self.b, top.b),
+        }
+    }
+}
+
+impl RGB {
+    pub fn blend(self, top: RGB, mode: BlendMode, opacity: f32) -> RGB {
+        RGBf::from(self).blend(top.into(), mode, opacity).into()
+    }
+}
+
+// Ways of compositing one color on top of another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendMode {
+    // Plain alpha compositing, the top color replaces the one below.
+    Over,
+    // Darkens, black on either side gives black and white leaves the other color alone.
+    Multiply,
+    // Inverse of multiply, lightens without clipping as quickly as adding.
+    Screen,
+    // Sums the channels, saturating at full brightness.
+    Add,
+    // The brighter of the two, per channel.
+    Max,
+}
+
+impl std::str::FromStr for BlendMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "over" => Ok(BlendMode::Over),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            "add" => Ok(BlendMode::Add),
+            "max" => Ok(BlendMode::Max),
+            _ => Err(format!("unknown blend mode `{}`, expected one of: over, multiply, screen, add, max", s)),
+        }
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (f32::min(c, 1.0).max(0.0) * 255.0).round() as u8
+}
+
+/// Björn Ottosson's OKLab perceptual color space. Blending here keeps lightness and hue
+/// even, without the dark, muddy midpoints of mixing sRGB values directly.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Oklab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl Oklab {
+    pub fn lerp(self, rhs: Oklab, t: f32) -> Oklab {
+        Oklab {
+            l: self.l + (rhs.l - self.l) * t,
+            a: self.a + (rhs.a - self.a) * t,
+            b: self.b + (rhs.b - self.b) * t,
+        }
+    }
+}
+
+impl From<RGB> for Oklab {
+    fn from(c: RGB) -> Self {
+        let (r, g, b) = (srgb_to_linear(c.r), srgb_to_linear(c.g), srgb_to_linear(c.b));
+
+        let l = (0.41222146 * r + 0.53633255 * g + 0.051445995 * b).cbrt();
+        let m = (0.2119035 * r + 0.6806995 * g + 0.10739696 * b).cbrt();
+        let s = (0.08830246 * r + 0.28171885 * g + 0.6299787 * b).cbrt();
+
+        Oklab {
+            l: 0.21045426 * l + 0.7936178 * m - 0.004072047 * s,
+            a: 1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+            b: 0.025904037 * l + 0.78277177 * m - 0.80867577 * s,
+        }
+    }
+}
+
+impl From<Oklab> for RGB {
+    fn from(c: Oklab) -> Self {
+        let l = (c.l + 0.39633778 * c.a + 0.21580376 * c.b).powi(3);
+        let m = (c.l - 0.105561346 * c.a - 0.06385417 * c.b).powi(3);
+        let s = (c.l - 0.08948418 * c.a - 1.2914855 * c.b).powi(3);
+
+        RGB {
+            r: linear_to_srgb(4.0767417 * l - 3.3077116 * m + 0.23096994 * s),
+            g: linear_to_srgb(-1.268438 * l + 2.6097574 * m - 0.34131938 * s),
+            b: linear_to_srgb(-0.0041960864 * l - 0.7034186 * m + 1.7076147 * s),
+        }
+    }
+}
+
+fn lerp_hue(a: f32, b: f32, t: f32) -> f32 {
+    // Go round the shorter way.
+    let delta = (b - a + 540.0) % 360.0 - 180.0;
+    (a + delta * t).rem_euclid(360.0)
+}
+
+/// Hue (degrees), saturation and value, all but hue in 0..1.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Hsv {
+    pub h: f32,
+    pub s: f32,
+    pub v: f32,
+}
+
+impl Hsv {
+    pub fn rotate_hue(self, degrees: f32) -> Hsv {
+        Hsv { h: (self.h + degrees).rem_euclid(360.0), ..self }
+    }
+
+    pub fn lerp(self, rhs: Hsv, t: f32) -> Hsv {
+        Hsv {
+            h: lerp_hue(self.h, rhs.h, t),
+            s: self.s + (rhs.s - self.s) * t,
+            v: self.v + (rhs.v - self.v) * t,
+        }
+    }
+}
+
+// Hue in degrees plus the max and min channel, shared by the HSV and HSL conversions.
+fn hue_max_min(c: RGB) -> (f32, f32, f32) {
+    let (r, g, b) = (c.r as f32 / 255.0, c.g as f32 / 255.0, c.b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    (h, max, min)
+}
+
+// Builds a color from hue, chroma and the amount to lift every channel by.
+fn from_hue_chroma(h: f32, chroma: f32, m: f32) -> RGB {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let to_u8 = |c: f32| (f32::min(c + m, 1.0).max(0.0) * 255.0).round() as u8;
+    RGB::new(to_u8(r), to_u8(g), to_u8(b))
+}
+
+impl From<RGB> for Hsv {
+    fn from(c: RGB) -> Self {
+        let (h, max, min) = hue_max_min(c);
+        Hsv {
+            h,
+            s: if max > 0.0 { (max - min) / max } else { 0.0 },
+            v: max,
+        }
+    }
+}
+
+impl From<Hsv> for RGB {
+    fn from(c: Hsv) -> Self {
+        let chroma = c.v * c.s;
+        from_hue_chroma(c.h, chroma, c.v - chroma)
+    }
+}
+
+/// Hue (degrees), saturation and lightness, all but hue in 0..1.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Hsl {
+    pub h: f32,
+    pub s: f32,
+    pub l: f32,
+}
+
+impl Hsl {
+    pub fn rotate_hue(self, degrees: f32) -> Hsl {
+        Hsl { h: (self.h + degrees).rem_euclid(360.0), ..self }
+    }
+
+    pub fn lerp(self, rhs: Hsl, t: f32) -> Hsl {
+        Hsl {
+            h: lerp_hue(self.h, rhs.h, t),
+            s: self.s + (rhs.s - self.s) * t,
+            l: self.l + (rhs.l - self.l) * t,
+        }
+    }
+}
+
+impl From<RGB> for Hsl {
+    fn from(c: RGB) -> Self {
+        let (h, max, min) = hue_max_min(c);
+        let l = (max + min) / 2.0;
+        let delta = max - min;
+        Hsl {
+            h,
+            s: if delta > 0.0 { delta / (1.0 - (2.0 * l - 1.0).abs()) } else { 0.0 },
+            l,
+        }
+    }
+}
+
+impl From<Hsl> for RGB {
+    fn from(c: Hsl) -> Self {
+        let chroma = (1.0 - (2.0 * c.l - 1.0).abs()) * c.s;
+        from_hue_chroma(c.h, chroma, c.l - chroma / 2.0)
+    }
+}
+
+/// Polar form of OKLab, lightness, chroma and hue in degrees.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Oklch {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+impl Oklch {
+    pub fn rotate_hue(self, degrees: f32) -> Oklch {
+        Oklch { h: (self.h + degrees).rem_euclid(360.0), ..self }
+    }
+
+    pub fn lerp(self, rhs: Oklch, t: f32) -> Oklch {
+        Oklch {
+            l: self.l + (rhs.l - self.l) * t,
+            c: self.c + (rhs.c - self.c) * t,
+            h: lerp_hue(self.h, rhs.h, t),
+        }
+    }
+}
+
+impl From<Oklab> for Oklch {
+    fn from(c: Oklab) -> Self {
+        Oklch {
+            l: c.l,
+            c: c.a.hypot(c.b),
+            h: c.b.atan2(c.a).to_degrees().rem_euclid(360.0),
+        }
+    }
+}
+
+impl From<Oklch> for Oklab {
+    fn from(c: Oklch) -> Self {
+        let h = c.h.to_radians();
+        Oklab {
+            l: c.l,
+            a: c.c * h.cos(),
+            b: c.c * h.sin(),
+        }
+    }
+}
+
+impl From<RGB> for Oklch {
+    fn from(c: RGB) -> Self {
+        Oklab::from(c).into()
+    }
+}
+
+impl From<Oklch> for RGB {
+    fn from(c: Oklch) -> Self {
+        Oklab::from(c).into()
+    }
+}
+
+/// Color space to blend or rotate hue in.
+#[derive(Debug, Clone, Copy)]
+pub enum ColorSpace {
+    Rgb,
+    Hsv,
+    Hsl,
+    Oklab,
+    Oklch,
+}
+
+impl std::str::FromStr for ColorSpace {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rgb" => Ok(ColorSpace::Rgb),
+            "hsv" => Ok(ColorSpace::Hsv),
+            "hsl" => Ok(ColorSpace::Hsl),
+            "oklab" => Ok(ColorSpace::Oklab),
+            "oklch" => Ok(ColorSpace::Oklch),
+            _ => Err(format!("unknown color space `{}`, expected one of: rgb, hsv, hsl, oklab, oklch", s)),
+        }
+    }
+}
+
+impl ColorSpace {
+    pub fn lerp(self, a: RGB, b: RGB, t: f32) -> RGB {
+        match self {
+            ColorSpace::Rgb => {
+                let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+                RGB::new(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b))
+            }
+            ColorSpace::Hsv => Hsv::from(a).lerp(b.into(), t).into(),
+            ColorSpace::Hsl => Hsl::from(a).lerp(b.into(), t).into(),
+            ColorSpace::Oklab => Oklab::from(a).lerp(b.into(), t).into(),
+            ColorSpace::Oklch => Oklch::from(a).lerp(b.into(), t).into(),
+        }
+    }
+
+    // RGB has no hue of its own, so it rotates through HSV, and OKLab through OKLCh.
+    pub fn rotate_hue(self, c: RGB, degrees: f32) -> RGB {
+        match self {
+            ColorSpace::Rgb | ColorSpace::Hsv => Hsv::from(c).rotate_hue(degrees).into(),
+            ColorSpace::Hsl => Hsl::from(c).rotate_hue(degrees).into(),
+            ColorSpace::Oklab | ColorSpace::Oklch => Oklch::from(c).rotate_hue(degrees).into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<RGB, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn hex_forms() {
+        assert_eq!(parse("#FF8000"), Ok(RGB::new(255, 128, 0)));
+        assert_eq!(parse("ff8000"), Ok(RGB::new(255, 128, 0)));
+        assert_eq!(parse("#F0A"), Ok(RGB::new(255, 0, 170)));
+        assert_eq!(parse("f0a"), Ok(RGB::new(255, 0, 170)));
+        assert_eq!(parse("#000000"), Ok(RGB::new(0, 0, 0)));
+    }
+
+    #[test]
+    fn hex_wrong_length() {
+        assert!(parse("#FF00F").is_err());
+        assert!(parse("FF00FF0").is_err());
+        assert!(parse("#").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("#GG0000").is_err());
+    }
+
+    #[test]
+    fn rgb_function() {
+        assert_eq!(parse("rgb(255, 128, 0)"), Ok(RGB::new(255, 128, 0)));
+        assert_eq!(parse("rgb(100%,50%,0%)"), Ok(RGB::new(255, 128, 0)));
+        assert!(parse("rgb(256, 0, 0)").is_err());
+        assert!(parse("rgb(-1, 0, 0)").is_err());
+        assert!(parse("rgb(101%, 0, 0)").is_err());
+        assert!(parse("rgb(1, 2)").is_err());
+        assert!(parse("rgb(1, 2, 3, 4)").is_err());
+        assert!(parse("rgb(1, 2, 3").is_err());
+    }
+
+    #[test]
+    fn hsv_and_hsl_functions() {
+        assert_eq!(parse("hsv(120, 1, 1)"), Ok(RGB::new(0, 255, 0)));
+        assert_eq!(parse("hsv(300, 100%, 50%)"), Ok(RGB::new(128, 0, 128)));
+        assert_eq!(parse("hsl(240deg, 100%, 50%)"), Ok(RGB::new(0, 0, 255)));
+        // Hue wraps around the wheel.
+        assert_eq!(parse("hsv(480, 1, 1)"), parse("hsv(120, 1, 1)"));
+        assert!(parse("hsv(0, 1.5, 1)").is_err());
+        assert!(parse("hsl(0, 1, 101%)").is_err());
+        assert!(parse("hsv(x, 1, 1)").is_err());
+        assert!(parse("cmyk(0, 0, 0)").is_err());
+    }
+
+    #[test]
+    fn named_colors() {
+        assert_eq!(parse("red"), Ok(RGB::new(255, 0, 0)));
+        assert_eq!(parse("RebeccaPurple"), Ok(RGB::new(102, 51, 153)));
+        assert_eq!(parse("  white "), Ok(RGB::new(255, 255, 255)));
+        assert_eq!(parse(" RGB( 1 , 2 , 3 ) "), Ok(RGB::new(1, 2, 3)));
+        assert!(parse("blurple").is_err());
+    }
+
+    #[test]
+    fn saturate_black() {
+        assert_eq!(RGB::new(0, 0, 0).saturate(), RGB::new(0, 0, 0));
+        assert_eq!(RGB::new(0, 64, 128).saturate(), RGB::new(0, 127, 255));
+    }
+
+    #[test]
+    fn split_list_keeps_parentheses() {
+        assert_eq!(split_list("rgb(1,2,3),0.5:red,#00F"), vec!["rgb(1,2,3)", "0.5:red", "#00F"]);
+        assert_eq!(split_list("hsv(0,1,1)"), vec!["hsv(0,1,1)"]);
+        assert_eq!(split_list("red"), vec!["red"]);
+    }
+
+    #[test]
+    fn multiply_channels() {
+        assert_eq!(RGB::new(255, 128, 64) * RGB::new(200, 100, 50), RGB::new(200, 50, 12));
+        assert_eq!(RGB::new(255, 255, 255) * RGB::new(1, 2, 3), RGB::new(1, 2, 3));
+    }
+
+    #[test]
+    fn blend_modes() {
+        let below = RGB::new(200, 100, 50);
+        let top = RGB::new(255, 128, 64);
+        assert_eq!(below.blend(top, BlendMode::Over, 1.0), RGB::new(255, 128, 64));
+        assert_eq!(below.blend(top, BlendMode::Multiply, 1.0), RGB::new(200, 50, 13));
+        assert_eq!(below.blend(top, BlendMode::Screen, 1.0), RGB::new(255, 178, 101));
+        assert_eq!(below.blend(top, BlendMode::Add, 1.0), RGB::new(255, 228, 114));
+        assert_eq!(below.blend(top, BlendMode::Max, 1.0), RGB::new(255, 128, 64));
+        // Opacity fades between the color below and the full effect.
+        assert_eq!(below.blend(top, BlendMode::Over, 0.5), RGB::new(228, 114, 57));
+        assert_eq!(below.blend(top, BlendMode::Add, 0.0), below);
+    }
 }
\ No newline at end of file
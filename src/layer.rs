@@ -0,0 +1,198 @@
+use super::color::{BlendMode, RGBf};
+use std::time::{Duration, Instant};
+
+// What a layer draws.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerKind {
+    // A solid ambient color under everything else.
+    Background,
+    // The spectrum bars, and the band levels on the boom segment.
+    Spectrum,
+    // Held peaks, one dot per LED above its bar.
+    Peaks,
+    // The whole strip flashing on beats.
+    Flash,
+    // Transient overlays such as the startup flash.
+    Notify,
+}
+
+impl std::str::FromStr for LayerKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "background" => Ok(LayerKind::Background),
+            "spectrum" => Ok(LayerKind::Spectrum),
+            "peaks" => Ok(LayerKind::Peaks),
+            "flash" => Ok(LayerKind::Flash),
+            "notify" => Ok(LayerKind::Notify),
+            _ => Err(format!("unknown layer `{}`, expected one of: background, spectrum, peaks, flash, notify", s)),
+        }
+    }
+}
+
+// A layer as given on the command line, `kind[:blend[:opacity]]`.
+#[derive(Debug, Clone, Copy)]
+pub struct Layer {
+    pub kind: LayerKind,
+    pub blend: BlendMode,
+    pub opacity: f32,
+}
+
+impl Layer {
+    pub fn new(kind: LayerKind) -> Self {
+        Layer {
+            kind,
+            blend: BlendMode::Over,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl std::str::FromStr for Layer {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(':').collect();
+        if fields.len() > 3 {
+            return Err(format!("layer `{}` should be kind[:blend[:opacity]]", s));
+        }
+
+        let mut layer = Layer::new(fields[0].parse()?);
+        if let Some(blend) = fields.get(1) {
+            layer.blend = blend.parse()?;
+        }
+        if let Some(opacity) = fields.get(2) {
+            layer.opacity = opacity.parse().map_err(|e| format!("bad layer opacity `{}`: {}", opacity, e))?;
+            if !(0.0..=1.0).contains(&layer.opacity) {
+                return Err(format!("layer opacity `{}` should be between 0 and 1", opacity));
+            }
+        }
+        Ok(layer)
+    }
+}
+
+// What one layer drew over one segment of the strip. LEDs the layer doesn't cover are left
+// with zero alpha, so the layers below show through.
+pub struct Buffer {
+    color: Vec<RGBf>,
+    alpha: Vec<f32>,
+}
+
+impl Buffer {
+    fn new(len: usize) -> Self {
+        Buffer {
+            color: vec![RGBf::default(); len],
+            alpha: vec![0.0; len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.color.len()
+    }
+
+    pub fn set(&mut self, idx: usize, color: RGBf, alpha: f32) {
+        self.color[idx] = color;
+        self.alpha[idx] = alpha;
+    }
+
+    pub fn fill(&mut self, color: RGBf, alpha: f32) {
+        for idx in 0..self.len() {
+            self.set(idx, color, alpha);
+        }
+    }
+
+    // Draws `color` with its brightness as the coverage, so over black it comes out as given
+    // while dark LEDs let the layers below show through.
+    pub fn set_lit(&mut self, idx: usize, color: RGBf) {
+        let alpha = color.r.max(color.g).max(color.b) / 255.0;
+        if alpha > 0.0 {
+            self.set(idx, alpha.recip() * color, alpha);
+        } else {
+            self.set(idx, color, 0.0);
+        }
+    }
+
+    pub fn fill_lit(&mut self, color: RGBf) {
+        for idx in 0..self.len() {
+            self.set_lit(idx, color);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.fill(RGBf::default(), 0.0);
+    }
+
+    // Composites this buffer onto `out` through `layer`'s blend mode and opacity.
+    fn composite(&self, layer: &Layer, out: &mut [RGBf]) {
+        for ((o, &c), &a) in out.iter_mut().zip(self.color.iter()).zip(self.alpha.iter()) {
+            *o = o.blend(c, layer.blend, layer.opacity * a);
+        }
+    }
+}
+
+// Stacks the layers bottom to top, separately over the spectrum and the boom segments.
+pub struct Compositor {
+    layers: Vec<(Layer, Buffer, Buffer)>,
+    spectrum_len: usize,
+    boom_len: usize,
+}
+
+impl Compositor {
+    pub fn new(layers: &[Layer], spectrum_len: usize, boom_len: usize) -> Self {
+        Compositor {
+            layers: layers.iter()
+                .map(|&layer| (layer, Buffer::new(spectrum_len), Buffer::new(boom_len)))
+                .collect(),
+            spectrum_len,
+            boom_len,
+        }
+    }
+
+    // Has `draw` fill in each layer's spectrum and boom buffers, then blends them down into
+    // `spectrum` and `boom` starting from black.
+    pub fn render<F>(&mut self, spectrum: &mut Vec<RGBf>, boom: &mut Vec<RGBf>, mut draw: F)
+        where F: FnMut(&Layer, &mut Buffer, &mut Buffer)
+    {
+        spectrum.clear();
+        spectrum.resize(self.spectrum_len, RGBf::default());
+        boom.clear();
+        boom.resize(self.boom_len, RGBf::default());
+
+        for (layer, s, b) in self.layers.iter_mut() {
+            s.clear();
+            b.clear();
+            draw(layer, s, b);
+            s.composite(layer, spectrum);
+            b.composite(layer, boom);
+        }
+    }
+}
+
+// A color shown over the strip for a while, fading out as it expires.
+pub struct Notification {
+    color: RGBf,
+    start: Instant,
+    duration: Duration,
+}
+
+impl Notification {
+    pub fn new(color: RGBf, duration: f32) -> Result<Self, String> {
+        let duration = Duration::try_from_secs_f32(duration)
+            .map_err(|_| format!("notification duration {} is not a valid number of seconds", duration))?;
+
+        Ok(Notification {
+            color,
+            start: Instant::now(),
+            duration,
+        })
+    }
+
+    // The color and its alpha right now, `None` once it has run out.
+    pub fn current(&self) -> Option<(RGBf, f32)> {
+        let elapsed = self.start.elapsed();
+        if elapsed >= self.duration {
+            None
+        } else {
+            Some((self.color, 1.0 - elapsed.as_secs_f32() / self.duration.as_secs_f32()))
+        }
+    }
+}
@@ -3,17 +3,24 @@ use rustfft::num_complex::Complex;
 use rustfft::*;
 use serialport::prelude::*;
 use std::collections::VecDeque;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
 
+mod beat;
 mod color;
+mod layer;
 mod math;
+mod output;
+mod palette;
+mod pipeline;
 mod process;
+mod ramp;
 mod strided_chunks;
 
-use color::RGB;
-use math::{BlackmanHarrisWindow, Window, NutallWindow, f_to_bin};
-use process::{Process, ProcessMut};
+use color::{RGB, RGBf};
+use math::{BlackmanHarrisWindow, EqCurve, Window, NutallWindow, f_to_bin};
+use pipeline::{Pipeline, Stage};
+use process::{LedMap, ProcessMut};
 
 #[derive(StructOpt, Debug, Clone)]
 struct Args {
@@ -23,61 +30,662 @@ struct Args {
     com_port: String,
     #[structopt(default_value = "0.04", long = "decay")]
     decay_time: f32,
+    #[structopt(default_value = "0", long = "attack")]
+    attack_time: f32,
+    #[structopt(default_value = "peak", long = "envelope")]
+    envelope: process::Detector,
     // #[structopt(default_value = "1", long = "fftscale")]
     // fftscale: f32,
     #[structopt(default_value = "8000", long = "mf")]
     mf: f32,
+    #[structopt(default_value = "20", long = "min-freq")]
+    min_freq: f32,
+    #[structopt(default_value = "20", long = "hpf")]
+    hpf_cutoff: f32,
     #[structopt(long = "fft", default_value = "1536")]
     fft_size: usize,
     #[structopt(long = "overlap", default_value = "0.5")]
     overlap: f32,
     #[structopt(long = "exp", default_value = "1.0")]
     exp: f32,
+    #[structopt(long = "db")]
+    db: bool,
+    #[structopt(long = "db-floor", default_value = "-60")]
+    db_floor: f32,
+    #[structopt(long = "db-ceil", default_value = "0")]
+    db_ceil: f32,
     #[structopt(long = "agct", default_value = "1")]
     agc_target: f32,
-    #[structopt(long = "agclen", default_value = "1")]
-    agc_len: usize,
+    #[structopt(long = "agc-rise", default_value = "0")]
+    agc_rise: f32,
+    #[structopt(long = "agc-fall", default_value = "1.0")]
+    agc_fall: f32,
+    #[structopt(long = "agc-max-gain", default_value = "24")]
+    agc_max_gain: f32,
+    #[structopt(long = "agc-band")]
+    agc_bands: Vec<AgcBand>,
     #[structopt(long = "color", default_value = "FF00FF")]
-    color: String,
+    color: RGB,
+    #[structopt(long = "palette")]
+    palette: Option<palette::Gradient>,
+    #[structopt(long = "palette-mode", default_value = "position")]
+    palette_mode: palette::PaletteMode,
+    #[structopt(long = "palette-space", default_value = "oklab")]
+    palette_space: color::ColorSpace,
+    #[structopt(long = "hue-cycle", default_value = "0")]
+    hue_cycle: f32,
+    #[structopt(long = "hue-space", default_value = "oklch")]
+    hue_space: color::ColorSpace,
     #[structopt(long = "noagc")]
     no_agc: bool,
-    #[structopt(long = "hdr")]
-    hdr: bool,
-    #[structopt(long = "shdr")]
-    super_hdr: bool,
+    #[structopt(long = "pipeline", use_delimiter = true, default_value = "eq,agc,scale,envelope,floor,freq-blur,map,smooth")]
+    pipeline: Vec<String>,
+    #[structopt(long = "ramp")]
+    ramp: Option<ramp::IntensityRamp>,
+    #[structopt(long = "ramp-blend", default_value = "stack")]
+    ramp_blend: ramp::RampBlend,
+    #[structopt(long = "boom-ramp")]
+    boom_ramp: Option<ramp::IntensityRamp>,
+    #[structopt(long = "boom-ramp-blend", default_value = "stack")]
+    boom_ramp_blend: ramp::RampBlend,
     #[structopt(long = "preagc")]
     preagc: bool,
-    #[structopt(default_value = "3", long = "boom")]
-    boom_count: usize,
+    #[structopt(long = "band")]
+    bands: Vec<BandSpec>,
     #[structopt(default_value = "FF0000", long = "boom-color")]
-    boom_color: String,
-    #[structopt(default_value = "1.0", long = "lincor")]
-    lin_cor: f32
+    boom_color: RGB,
+    #[structopt(long = "beats")]
+    beats: bool,
+    #[structopt(default_value = "1.5", long = "beat-sensitivity")]
+    beat_sensitivity: f32,
+    #[structopt(default_value = "1.0", long = "beat-window")]
+    beat_window: f32,
+    #[structopt(default_value = "0.15", long = "beat-decay")]
+    beat_decay: f32,
+    #[structopt(default_value = "0", long = "tempo-pulse")]
+    tempo_pulse: f32,
+    #[structopt(long = "stats")]
+    stats: bool,
+    #[structopt(default_value = "0", long = "tilt")]
+    tilt: f32,
+    #[structopt(long = "eq", use_delimiter = true)]
+    eq: Vec<EqPoint>,
+    #[structopt(default_value = "log", long = "map")]
+    map: MapKind,
+    #[structopt(default_value = "0", long = "smooth")]
+    smooth: f32,
+    #[structopt(default_value = "0", long = "freq-blur")]
+    freq_blur: f32,
+    #[structopt(long = "print-map")]
+    print_map: bool,
+    #[structopt(long = "gamma", default_value = "1.0")]
+    gamma: Gamma,
+    #[structopt(long = "white-balance", default_value = "1.0,1.0,1.0")]
+    white_balance: PerChannel,
+    #[structopt(long = "calibration")]
+    calibration: Option<ColorMatrix>,
+    #[structopt(long = "test-pattern")]
+    test_pattern: bool,
+    #[structopt(long = "nodither")]
+    no_dither: bool,
+    #[structopt(default_value = "1.0", long = "max-brightness")]
+    max_brightness: f32,
+    #[structopt(default_value = "0", long = "power-budget")]
+    power_budget: f32,
+    #[structopt(long = "ma-per-channel", default_value = "20")]
+    ma_per_channel: PerChannel,
+    #[structopt(default_value = "spectrum", long = "mode")]
+    mode: Mode,
+    #[structopt(default_value = "30", long = "scroll-speed")]
+    scroll_speed: f32,
+    #[structopt(long = "layers", use_delimiter = true)]
+    layers: Vec<layer::Layer>,
+    #[structopt(long = "background", default_value = "000000")]
+    background: RGB,
+    #[structopt(long = "flash-color", default_value = "FFFFFF")]
+    flash_color: RGB,
+    #[structopt(long = "notify-color", default_value = "FFFFFF")]
+    notify_color: RGB,
+    #[structopt(default_value = "0.5", long = "startup-flash")]
+    startup_flash: f32,
+    #[structopt(long = "peaks")]
+    peaks: bool,
+    #[structopt(default_value = "FFFFFF", long = "peak-color")]
+    peak_color: RGB,
+    #[structopt(default_value = "0.5", long = "peak-hold")]
+    peak_hold: f32,
+    #[structopt(default_value = "4.0", long = "peak-gravity")]
+    peak_gravity: f32,
+    #[structopt(default_value = "-70", long = "gate")]
+    gate_threshold: f32,
+    #[structopt(default_value = "6", long = "gate-hysteresis")]
+    gate_hysteresis: f32,
+    #[structopt(default_value = "0.25", long = "gate-hold")]
+    gate_hold: f32,
+    #[structopt(default_value = "10", long = "idle-after")]
+    idle_after: f32,
+    #[structopt(default_value = "off", long = "idle")]
+    idle: IdleEffect,
+    #[structopt(default_value = "202020", long = "idle-color")]
+    idle_color: RGB,
+}
+
+// One band of the multi-band AGC, given as `max_hz:target:fall_seconds`. Bands are listed
+// in ascending order and each one starts where the previous one ended.
+#[derive(Debug, Clone, Copy)]
+struct AgcBand {
+    max_freq: f32,
+    target: f32,
+    fall: f32,
+}
+
+impl std::str::FromStr for AgcBand {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(':').collect();
+        if fields.len() != 3 {
+            return Err(format!("AGC band `{}` should be max_hz:target:fall_seconds", s));
+        }
+
+        let max_freq = fields[0].parse().map_err(|e| format!("bad AGC band frequency `{}`: {}", fields[0], e))?;
+        let target = fields[1].parse().map_err(|e| format!("bad AGC band target `{}`: {}", fields[1], e))?;
+        let fall = fields[2].parse().map_err(|e| format!("bad AGC band fall time `{}`: {}", fields[2], e))?;
+
+        Ok(AgcBand { max_freq, target, fall })
+    }
+}
+
+// A named frequency band driving its own part of the boom segment, given as
+// `name:lo_hz-hi_hz` or `name:lo_hz-hi_hz:color`. Without a color `--boom-color` is used.
+#[derive(Debug, Clone)]
+struct BandSpec {
+    name: String,
+    lo: f32,
+    hi: f32,
+    color: Option<RGB>,
+}
+
+impl std::str::FromStr for BandSpec {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(':').collect();
+        if fields.len() < 2 || fields.len() > 3 {
+            return Err(format!("band `{}` should be name:lo_hz-hi_hz[:color]", s));
+        }
+
+        let mut range = fields[1].splitn(2, '-');
+        let (lo, hi) = match (range.next(), range.next()) {
+            (Some(lo), Some(hi)) => (lo, hi),
+            _ => return Err(format!("band range `{}` should be lo_hz-hi_hz", fields[1])),
+        };
+        let lo: f32 = lo.parse().map_err(|e| format!("bad band frequency `{}`: {}", lo, e))?;
+        let hi: f32 = hi.parse().map_err(|e| format!("bad band frequency `{}`: {}", hi, e))?;
+        if lo >= hi {
+            return Err(format!("band `{}` is empty, {} Hz is not below {} Hz", fields[0], lo, hi));
+        }
+
+        Ok(BandSpec {
+            name: fields[0].to_string(),
+            lo,
+            hi,
+            color: fields.get(2).map(|c| c.parse()).transpose().map_err(|e| format!("bad band color: {}", e))?,
+        })
+    }
+}
+
+// Comma separated finite numbers.
+fn parse_values(s: &str) -> Result<Vec<f32>, String> {
+    s.split(',').map(|v| {
+        let v: f32 = v.trim().parse().map_err(|e| format!("bad value `{}`: {}", v, e))?;
+        if !v.is_finite() {
+            return Err(format!("value {} must be finite", v));
+        }
+        Ok(v)
+    }).collect()
+}
+
+// An `r,g,b` setting, or a single value for all three channels. None may be negative.
+#[derive(Debug, Clone, Copy)]
+struct PerChannel([f32; 3]);
+
+impl std::str::FromStr for PerChannel {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = parse_values(s)?;
+        if let Some(v) = values.iter().find(|&&v| v < 0.0) {
+            return Err(format!("value {} must be zero or positive", v));
+        }
+
+        match *values {
+            [v] => Ok(PerChannel([v, v, v])),
+            [r, g, b] => Ok(PerChannel([r, g, b])),
+            ref values => Err(format!("expected either 1 or 3 values, found {}", values.len())),
+        }
+    }
+}
+
+// Per channel gamma exponents, like `PerChannel` but each must be above zero, since a gamma
+// of zero would light black LEDs at full brightness.
+#[derive(Debug, Clone, Copy)]
+struct Gamma([f32; 3]);
+
+impl std::str::FromStr for Gamma {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let PerChannel(gamma) = s.parse()?;
+        if let Some(v) = gamma.iter().find(|&&v| v <= 0.0) {
+            return Err(format!("gamma {} must be above zero", v));
+        }
+        Ok(Gamma(gamma))
+    }
+}
+
+// A row major 3x3 color matrix, 9 comma separated values.
+#[derive(Debug, Clone, Copy)]
+struct ColorMatrix([[f32; 3]; 3]);
+
+impl std::str::FromStr for ColorMatrix {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match *parse_values(s)? {
+            [a, b, c, d, e, f, g, h, i] => Ok(ColorMatrix([[a, b, c], [d, e, f], [g, h, i]])),
+            ref values => Err(format!("expected 9 values for a row major 3x3 matrix, found {}", values.len())),
+        }
+    }
+}
+
+// A point on the EQ curve, given as `hz:db`.
+#[derive(Debug, Clone, Copy)]
+struct EqPoint(f32, f32);
+
+impl std::str::FromStr for EqPoint {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.splitn(2, ':');
+        let (f, gain) = match (fields.next(), fields.next()) {
+            (Some(f), Some(gain)) => (f, gain),
+            _ => return Err(format!("EQ point `{}` should be hz:db", s)),
+        };
+
+        let f: f32 = f.parse().map_err(|e| format!("bad EQ frequency `{}`: {}", f, e))?;
+        let gain: f32 = gain.parse().map_err(|e| format!("bad EQ gain `{}`: {}", gain, e))?;
+        if !(f.is_finite() && f > 0.0) {
+            return Err(format!("EQ frequency {} must be positive", f));
+        }
+        if !gain.is_finite() {
+            return Err(format!("EQ gain {} must be finite", gain));
+        }
+
+        Ok(EqPoint(f, gain))
+    }
+}
+
+// Frequency -> LED mapping scheme, selected with `--map`.
+#[derive(Debug, Clone, Copy)]
+enum MapKind {
+    Log,
+    Mel,
+    Bark,
+}
+
+impl std::str::FromStr for MapKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(MapKind::Log),
+            "mel" => Ok(MapKind::Mel),
+            "bark" => Ok(MapKind::Bark),
+            _ => Err(format!("unknown map `{}`, expected one of: log, mel, bark", s)),
+        }
+    }
+}
+
+// What the spectrum segment shows, selected with `--mode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    // One bar per LED from bass to treble, mirrored on both runs.
+    Spectrum,
+    // The spectrum folded out from the middle of the strip.
+    Center,
+    // Stereo level meter, left on the top run and right on the bottom.
+    Vu,
+    // Scrolling history of the overall level, colored by the spectral centroid.
+    Waterfall,
+    // The whole strip in one color following the overall level.
+    Wash,
+}
+
+impl std::str::FromStr for Mode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spectrum" => Ok(Mode::Spectrum),
+            "center" => Ok(Mode::Center),
+            "vu" => Ok(Mode::Vu),
+            "waterfall" => Ok(Mode::Waterfall),
+            "wash" => Ok(Mode::Wash),
+            _ => Err(format!("unknown mode `{}`, expected one of: spectrum, center, vu, waterfall, wash", s)),
+        }
+    }
+}
+
+// What the strip shows once the audio has been silent for `--idle-after` seconds.
+#[derive(Debug, Clone, Copy)]
+enum IdleEffect {
+    Off,
+    Static,
+    Breathe,
+}
+
+impl std::str::FromStr for IdleEffect {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(IdleEffect::Off),
+            "static" => Ok(IdleEffect::Static),
+            "breathe" => Ok(IdleEffect::Breathe),
+            _ => Err(format!("unknown idle effect `{}`, expected one of: off, static, breathe", s)),
+        }
+    }
+}
+
+impl IdleEffect {
+    fn render(self, color: RGBf, t: f32) -> RGBf {
+        match self {
+            IdleEffect::Off => RGBf::default(),
+            IdleEffect::Static => color,
+            IdleEffect::Breathe => {
+                // One breath every four seconds, raised cosine so it eases in and out.
+                let phase = 2.0 * std::f32::consts::PI * t / 4.0;
+                (0.5 - 0.5 * phase.cos()) * color
+            }
+        }
+    }
 }
 
 const NUM_LEDS: usize = 82;
+const BOOM_LEDS: usize = 50;
+// Everything on the wire, the spectrum and boom segments for both the top and bottom runs.
+const WIRE_LEDS: usize = 2 * (NUM_LEDS + BOOM_LEDS);
+
+// One processed FFT frame, handed from the FFT thread to the display loop.
+struct Frame {
+    leds: Vec<f32>,
+    peaks: Vec<f32>,
+    // Level of each `--band`.
+    bands: Vec<f32>,
+    // Set while the noise gate is closed.
+    gated: bool,
+    tempo: Option<beat::Tempo>,
+    // Beat flash over the whole spectrum, 0..1.
+    flash: f32,
+    // Left and right channel levels, 0..1 on the `--db-floor`..`--db-ceil` scale.
+    vu: [f32; 2],
+}
 
 fn main() {
     let args = Args::from_args();
 
+    if args.print_map {
+        print_map(&args);
+        return;
+    }
+
+    if args.test_pattern {
+        run_test_pattern(&args);
+        return;
+    }
+
+    // Catch a bad `--pipeline` up front, rather than as a panic once everything is running.
+    let pipeline = match build_pipeline(&args) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            eprintln!("invalid pipeline: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let dc_block = match process::DcBlock::new(44100.0, args.hpf_cutoff) {
+        Ok(dc_block) => dc_block,
+        Err(e) => {
+            eprintln!("invalid --hpf: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // The VU meter always works in dB, whether or not the spectrum does.
+    let vu_scale = match process::DbScale::new(args.db_floor, args.db_ceil) {
+        Ok(vu_scale) => vu_scale,
+        Err(e) => {
+            eprintln!("invalid dB range: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     // We saturate the color to the maximum value while maintaing the hue
     // to get the maximum dynamic range of the LEDs.
-    let color = RGB::from_hex(&args.color)
+    let color = args.color
         .saturate();
 
-    let boom_color = RGB::from_hex(&args.boom_color);
+    let bands = band_specs(&args);
+    let band_colors: Vec<RGB> = bands.iter()
+        .map(|b| b.color.unwrap_or(args.boom_color))
+        .collect();
+    let palette = args.palette.clone().map(|p| p.in_space(args.palette_space));
+    let ramp = args.ramp.clone().map(|r| r.with_blend(args.ramp_blend));
+    let boom_ramp = args.boom_ramp.clone().map(|r| r.with_blend(args.boom_ramp_blend));
+    let peak_color = args.peak_color;
+    let idle_color = args.idle_color.into();
+    let background = args.background.into();
+    let flash_color = args.flash_color.into();
+
+    // Without `--layers` draw the bars over the background, topped with the peaks if asked for
+    // and the startup flash if there is one.
+    let mut layers = args.layers.clone();
+    if layers.is_empty() {
+        layers.push(layer::Layer::new(layer::LayerKind::Background));
+        layers.push(layer::Layer::new(layer::LayerKind::Spectrum));
+        if args.peaks {
+            layers.push(layer::Layer::new(layer::LayerKind::Peaks));
+        }
+        if args.startup_flash > 0.0 {
+            layers.push(layer::Layer::new(layer::LayerKind::Notify));
+        }
+    }
+    // The spectrum segment holds the top run followed by the bottom run, so stereo modes can
+    // draw them differently.
+    let mut compositor = layer::Compositor::new(&layers, 2 * NUM_LEDS, BOOM_LEDS);
+    let notification = if args.startup_flash > 0.0 {
+        match layer::Notification::new(args.notify_color.into(), args.startup_flash) {
+            Ok(notification) => Some(notification),
+            Err(e) => {
+                eprintln!("invalid --startup-flash: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
 
     let (audio_sender, audio_recv) = channel::bounded(args.fft_size);
     let _ = std::thread::spawn(move || audio_thread(audio_sender));
 
-    let (led_sender, led_recv) = channel::bounded(NUM_LEDS);
-    let (power_sender, power_recv) = channel::bounded(NUM_LEDS);
+    let (frame_sender, frame_recv) = channel::bounded(1);
     let cloned_args = args.clone();
-    let _ = std::thread::spawn(move || fft_thread(audio_recv, led_sender, power_sender, cloned_args));
+    let _ = std::thread::spawn(move || fft_thread(audio_recv, frame_sender, pipeline, dc_block, vu_scale, cloned_args));
 
 
     // Main thread takes care of sending the data down UART to micro for display.
-    let mut led_port = serialport::open_with_settings(
+    let mut led_port = open_port(&args);
+
+    let mut buf = Vec::with_capacity(BOOM_LEDS * 3 + BOOM_LEDS * 3 + NUM_LEDS * 4);
+    let mut frame = Vec::with_capacity(NUM_LEDS);
+    let mut power_data = Vec::with_capacity(BOOM_LEDS);
+    let mut output = build_output(&args);
+    let start = Instant::now();
+    let mut last_active = Instant::now();
+    let mut last_frame = Instant::now();
+    let mut waterfall: VecDeque<RGBf> = VecDeque::from(vec![RGBf::default(); NUM_LEDS]);
+    let mut scroll = 0.0;
+    loop {
+        // Time out so the idle effect keeps animating when the capture device stops
+        // delivering samples entirely.
+        let data = match frame_recv.recv_timeout(Duration::from_millis(50)) {
+            Ok(data) => Some(data),
+            Err(channel::RecvTimeoutError::Timeout) => None,
+            Err(channel::RecvTimeoutError::Disconnected) => break,
+        };
+
+        // Any frame through an open gate ends idle mode instantly.
+        if matches!(data, Some(ref d) if !d.gated) {
+            last_active = Instant::now();
+        }
+        let idle = last_active.elapsed().as_secs_f32() >= args.idle_after;
+
+        match data {
+            Some(ref data) if !idle => {
+                // Pulse along with the tracked tempo, peaking on the beat and fading until the next.
+                let pulse = data.tempo.map_or(0.0, |t| (1.0 - t.phase).powi(3)) * args.tempo_pulse;
+                let spectrum_scale = 1.0 - args.tempo_pulse + pulse;
+
+                // Slowly walk the hue of the spectrum colors around the wheel.
+                let hue = (args.hue_cycle * start.elapsed().as_secs_f32()) % 360.0;
+
+                // Color of the spectrum at `position` 0..1 from bass to treble, at level `b`.
+                let shade = |position: f32, b: f32| match ramp {
+                    Some(ref ramp) => ramp.at(b),
+                    None => {
+                        let color = palette.as_ref().map_or(color, |palette| palette.at(args.palette_mode.key(position, b)));
+                        let color = if hue != 0.0 { args.hue_space.rotate_hue(color, hue) } else { color };
+                        b * RGBf::from(color)
+                    }
+                };
+
+                // New energy enters at the bass end of the waterfall and scrolls along.
+                let now = Instant::now();
+                if args.mode == Mode::Waterfall {
+                    // Capped so coming back from idle doesn't scroll through the whole strip over and over.
+                    scroll = f32::min(scroll + args.scroll_speed * (now - last_frame).as_secs_f32(), NUM_LEDS as f32);
+                    let (level, centroid) = energy(&data.leds);
+                    while scroll >= 1.0 {
+                        waterfall.pop_back();
+                        waterfall.push_front(shade(centroid, level));
+                        scroll -= 1.0;
+                    }
+                }
+                last_frame = now;
+
+                let notice = notification.as_ref().and_then(|n| n.current());
+                compositor.render(&mut frame, &mut power_data, |layer, spectrum, boom| match layer.kind {
+                    layer::LayerKind::Background => {
+                        spectrum.fill(background, 1.0);
+                        boom.fill(background, 1.0);
+                    }
+                    layer::LayerKind::Spectrum => {
+                        match args.mode {
+                            Mode::Spectrum => for (idx, &b) in data.leds.iter().enumerate() {
+                                let led = shade(idx as f32 / (NUM_LEDS - 1) as f32, b * spectrum_scale);
+                                spectrum.set_lit(idx, led);
+                                spectrum.set_lit(NUM_LEDS + idx, led);
+                            },
+                            // Bass in the middle of the strip, treble out at both ends.
+                            Mode::Center => for idx in 0..NUM_LEDS {
+                                let position = (2.0 * idx as f32 - (NUM_LEDS - 1) as f32).abs() / (NUM_LEDS - 1) as f32;
+                                let led = shade(position, sample(&data.leds, position) * spectrum_scale);
+                                spectrum.set_lit(idx, led);
+                                spectrum.set_lit(NUM_LEDS + idx, led);
+                            },
+                            // Left channel on the top run, right on the bottom.
+                            Mode::Vu => for (run, &level) in data.vu.iter().enumerate() {
+                                let lit = level * spectrum_scale * NUM_LEDS as f32;
+                                for idx in 0..NUM_LEDS {
+                                    let fill = f32::min(lit - idx as f32, 1.0).max(0.0);
+                                    spectrum.set_lit(run * NUM_LEDS + idx, shade(idx as f32 / (NUM_LEDS - 1) as f32, fill));
+                                }
+                            },
+                            Mode::Waterfall => for (idx, &led) in waterfall.iter().enumerate() {
+                                spectrum.set_lit(idx, led);
+                                spectrum.set_lit(NUM_LEDS + idx, led);
+                            },
+                            Mode::Wash => {
+                                let (level, centroid) = energy(&data.leds);
+                                spectrum.fill_lit(shade(centroid, level * spectrum_scale));
+                            }
+                        }
+
+                        // The boom segment is split evenly between the bands, and keeps pulsing
+                        // through quiet passages.
+                        let mut led = 0;
+                        for (idx, (&level, &band_color)) in data.bands.iter().zip(band_colors.iter()).enumerate() {
+                            let power = f32::max(level, pulse);
+                            let power_color = boom_ramp.as_ref().map_or(power * RGBf::from(band_color), |ramp| ramp.at(power));
+                            let end = (idx + 1) * BOOM_LEDS / bands.len();
+                            while led < end {
+                                boom.set_lit(led, power_color);
+                                led += 1;
+                            }
+                        }
+                    }
+                    // Only the LEDs whose held peak sits above the bar get a dot.
+                    layer::LayerKind::Peaks => {
+                        if args.mode == Mode::Spectrum {
+                            for (idx, (&b, &p)) in data.leds.iter().zip(data.peaks.iter()).enumerate() {
+                                if p > b * spectrum_scale {
+                                    spectrum.set(idx, p * RGBf::from(peak_color), 1.0);
+                                    spectrum.set(NUM_LEDS + idx, p * RGBf::from(peak_color), 1.0);
+                                }
+                            }
+                        }
+                    }
+                    layer::LayerKind::Flash => {
+                        spectrum.fill(flash_color, data.flash);
+                        boom.fill(flash_color, data.flash);
+                    }
+                    layer::LayerKind::Notify => {
+                        if let Some((color, alpha)) = notice {
+                            spectrum.fill(color, alpha);
+                            boom.fill(color, alpha);
+                        }
+                    }
+                });
+            }
+            _ if idle => {
+                let idle_color = args.idle.render(idle_color, start.elapsed().as_secs_f32());
+                frame.clear();
+                frame.resize(2 * NUM_LEDS, idle_color);
+                power_data.clear();
+                power_data.resize(BOOM_LEDS, idle_color);
+            }
+            // Still waiting on the first frame after a stall, keep the last one on display.
+            _ => continue,
+        }
+
+        write_frame(&mut *led_port, &mut buf, &frame[..NUM_LEDS], &frame[NUM_LEDS..], &power_data, &mut output);
+    }
+}
+
+// Overall level of the LEDs, and the centroid of the spectrum as a position 0..1 along it.
+fn energy(leds: &[f32]) -> (f32, f32) {
+    let total: f32 = leds.iter().sum();
+    let rms = (leds.iter().map(|l| l * l).sum::<f32>() / leds.len() as f32).sqrt();
+    let centroid = if total > 0.0 {
+        leds.iter().enumerate().map(|(idx, l)| idx as f32 * l).sum::<f32>() / total / (leds.len() - 1) as f32
+    } else {
+        0.0
+    };
+    (rms, centroid)
+}
+
+// Linearly interpolated value at `position` 0..1 along `values`.
+fn sample(values: &[f32], position: f32) -> f32 {
+    let x = position * (values.len() - 1) as f32;
+    let idx = (x as usize).min(values.len() - 2);
+    let t = x - idx as f32;
+    values[idx] * (1.0 - t) + values[idx + 1] * t
+}
+
+fn open_port(args: &Args) -> Box<dyn SerialPort> {
+    serialport::open_with_settings(
         &args.com_port,
         &SerialPortSettings {
             baud_rate: 500000,
@@ -88,46 +696,223 @@ fn main() {
             timeout: Duration::from_millis(100),
         },
     )
-    .unwrap();
+    .unwrap()
+}
 
-    let mut buf = Vec::with_capacity(50 * 3 + 50 * 3 + NUM_LEDS * 4);
-    let mut frame = Vec::with_capacity(NUM_LEDS);
-    loop {
-        // Setup a channel iterator, then fill the framebuffer after clearing it.
-        let frame_iter = led_recv
-            .iter()
-            .take(NUM_LEDS)
-            .enumerate()
-            .map(|(_, b)| if args.super_hdr { color * RGB::super_hdr(b) } else if args.hdr { color * RGB::hdr(b) } else { b * color });
+// Sends one frame down the wire, the top run is wired in reverse.
+fn write_frame(
+    port: &mut dyn SerialPort,
+    buf: &mut Vec<u8>,
+    top: &[RGBf],
+    bottom: &[RGBf],
+    power_data: &[RGBf],
+    output: &mut output::Output,
+) {
+    buf.extend_from_slice(b"Ada");
+    buf.extend_from_slice(&[0x01, 0x06, 0x52]);
+
+    let top = top.iter().rev().chain(power_data.iter());
+    // Bottom
+    let bottom = bottom.iter().chain(power_data.iter());
+    output.encode(top.chain(bottom), buf);
+
+    let _ = port.write_all(buf);
+    let _ = port.flush();
+    buf.clear();
+}
 
-        frame.clear();
-        frame.extend(frame_iter);
+fn build_output(args: &Args) -> output::Output {
+    let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    let mut matrix = args.calibration.map_or(identity, |m| m.0);
 
-        let power_data : Vec<_> = power_recv.iter()
-            .take(50)
-            .map(|e| if args.super_hdr {RGB::super_hdr(e)} else {e * boom_color})
+    // White balance scales the output of the calibration matrix.
+    for (row, &scale) in matrix.iter_mut().zip(args.white_balance.0.iter()) {
+        row.iter_mut().for_each(|m| *m *= scale);
+    }
+
+    output::Output::new(
+        output::Calibration::new(matrix, args.gamma.0),
+        output::PowerLimit::new(args.max_brightness, args.ma_per_channel.0, args.power_budget),
+        output::Dither::new(WIRE_LEDS, !args.no_dither),
+    )
+}
+
+// Plays the calibration test pattern forever instead of visualizing audio.
+fn run_test_pattern(args: &Args) {
+    let mut led_port = open_port(args);
+    let mut buf = Vec::with_capacity(BOOM_LEDS * 3 + BOOM_LEDS * 3 + NUM_LEDS * 4);
+    let mut output = build_output(args);
+    let start = Instant::now();
+    loop {
+        let t = start.elapsed().as_secs_f32();
+        let frame: Vec<RGBf> = (0..NUM_LEDS)
+            .map(|idx| output::test_pattern(t, idx as f32 / (NUM_LEDS - 1) as f32))
+            .collect();
+        let power_data: Vec<RGBf> = (0..BOOM_LEDS)
+            .map(|idx| output::test_pattern(t, idx as f32 / (BOOM_LEDS - 1) as f32))
             .collect();
 
-        buf.extend_from_slice(b"Ada");
-        buf.extend_from_slice(&[0x01, 0x06, 0x52]);
+        write_frame(&mut *led_port, &mut buf, &frame, &frame, &power_data, &mut output);
+        std::thread::sleep(Duration::from_millis(16));
+    }
+}
 
-        buf.extend(frame.iter().rev().flat_map(color::RGB::as_slice));
-        buf.extend(power_data.iter().flat_map(color::RGB::as_slice));
-        // Bottom
-        buf.extend(frame.iter().flat_map(color::RGB::as_slice));
-        buf.extend(power_data.iter().flat_map(color::RGB::as_slice));
+fn build_led_map(args: &Args) -> Result<Box<dyn LedMap + Send>, String> {
+    let map: Box<dyn LedMap + Send> = match args.map {
+        MapKind::Log => Box::new(process::PageLog::new(args.fft_size, 44100.0, args.min_freq, args.mf, NUM_LEDS)?),
+        MapKind::Mel => Box::new(process::MelMap::new(args.fft_size, 44100.0, args.min_freq, args.mf, NUM_LEDS)?),
+        MapKind::Bark => Box::new(process::BarkMap::new(args.fft_size, 44100.0, args.min_freq, args.mf, NUM_LEDS)?),
+    };
+    Ok(map)
+}
 
-        let _ = led_port.write_all(&buf);
-        let _ = led_port.flush();
-        buf.clear();
+// The `--band`s, defaulting to a single kick drum band.
+fn band_specs(args: &Args) -> Vec<BandSpec> {
+    if args.bands.is_empty() {
+        vec!["kick:40-120".parse().unwrap()]
+    } else {
+        args.bands.clone()
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// Frames per second coming out of the FFT thread.
+fn frame_rate(args: &Args) -> f32 {
+    let overlap = (args.fft_size as f32 * args.overlap) as usize;
+    44100.0 / overlap as f32
+}
+
+// Assembles the spectrum -> LED pipeline in the order given by `--pipeline`.
+fn build_pipeline(args: &Args) -> Result<Pipeline, String> {
+    let fft_nyquist = args.fft_size / 2;
+    let mut pipeline = Pipeline::new(fft_nyquist);
+    for name in args.pipeline.iter().filter(|&name| !(args.no_agc && name == "agc")) {
+        let ctx = StageContext {
+            input_len: pipeline.output_len(),
+            fft_nyquist,
+            frame_rate: frame_rate(args),
+        };
+        pipeline.push(build_stage(name, args, &ctx)?);
+    }
+
+    if pipeline.output_len() != NUM_LEDS {
+        return Err(format!(
+            "pipeline must end with one value per LED, {} instead of {}, is `map` missing?",
+            pipeline.output_len(),
+            NUM_LEDS,
+        ));
+    }
+    Ok(pipeline)
+}
+
+// What a pipeline stage is being built for.
+struct StageContext {
+    // Length of the signal the stage will be fed.
+    input_len: usize,
+    fft_nyquist: usize,
+    frame_rate: f32,
+}
+
+// Builds the `--pipeline` stage called `name`. New stages only need an entry here.
+fn build_stage(name: &str, args: &Args, ctx: &StageContext) -> Result<Stage, String> {
+    let len = ctx.input_len;
+    let fft_size = args.fft_size;
+    let spectrum_only = || if len == ctx.fft_nyquist {
+        Ok(())
+    } else {
+        Err(format!("stage `{}` has to come before `map`", name))
+    };
+
+    let process: Box<dyn ProcessMut<f32, f32> + Send> = match name {
+        "eq" => {
+            spectrum_only()?;
+
+            let points: Vec<(f32, f32)> = args.eq.iter().map(|p| (p.0, p.1)).collect();
+            let eq_curve = EqCurve::new(args.tilt, &points);
+            Box::new(process::Equalizer::new(eq_curve.bin_gains(fft_size, 44100.0, len)))
+        }
+        "agc" => {
+            spectrum_only()?;
+
+            // Either one AGC over the whole spectrum, or one per `--agc-band`.
+            let agc_max_gain = db_to_gain(args.agc_max_gain);
+            if args.agc_bands.is_empty() {
+                let tracker = process::LevelTracker::new(ctx.frame_rate, args.agc_rise, args.agc_fall, args.agc_target);
+                Box::new(process::AGC::new(tracker, args.agc_target, agc_max_gain))
+            } else {
+                let last = args.agc_bands.len() - 1;
+                let mut start = 0;
+                let bands = args.agc_bands.iter().enumerate().map(|(idx, band)| {
+                    let end = if idx == last {
+                        len
+                    } else {
+                        f_to_bin(fft_size, 44100.0, band.max_freq).max(start).min(len)
+                    };
+                    let tracker = process::LevelTracker::new(ctx.frame_rate, args.agc_rise, band.fall, band.target);
+                    let agc = process::AGC::new(tracker, band.target, agc_max_gain);
+                    start = end;
+                    (end, agc)
+                }).collect();
+                Box::new(process::MultiBandAGC::new(bands))
+            }
+        }
+        "scale" => if args.db {
+            Box::new(process::DbScale::new(args.db_floor, args.db_ceil)?)
+        } else {
+            Box::new(process::Pow::new(args.exp))
+        },
+        "envelope" => Box::new(process::Envelope::new(
+            len,
+            ctx.frame_rate,
+            args.attack_time,
+            args.decay_time,
+            args.envelope,
+            1.0,
+        )),
+        // The dB scale already has its own floor.
+        "floor" => Box::new(process::Floor::new(if args.db { 0.0 } else { 0.001 })),
+        "freq-blur" => {
+            spectrum_only()?;
+            Box::new(process::GaussianBlur::new(args.freq_blur).map_err(|e| format!("--freq-blur: {}", e))?)
+        }
+        "map" => {
+            spectrum_only()?;
+            let map = build_led_map(args).map_err(|e| format!("invalid LED map: {}", e))?;
+            return Ok(Stage::new(name, Box::new(map), NUM_LEDS));
+        }
+        "smooth" => Box::new(process::GaussianBlur::new(args.smooth).map_err(|e| format!("--smooth: {}", e))?),
+        _ => return Err(format!("unknown pipeline stage `{}`", name)),
+    };
+
+    Ok(Stage::new(name, process, len))
+}
+
+// Dumps the frequency range covered by each LED for the current settings.
+fn print_map(args: &Args) {
+    let bin_width = 44100.0 / args.fft_size as f32;
+    println!("{:>4} {:>9} {:>9} {:>9} {:>9}", "led", "lo bin", "hi bin", "lo Hz", "hi Hz");
+    let map = match build_led_map(args) {
+        Ok(map) => map,
+        Err(e) => {
+            eprintln!("invalid LED map: {}", e);
+            std::process::exit(1);
+        }
+    };
+    for (led, (lo, hi)) in map.bin_table().into_iter().enumerate() {
+        println!("{:>4} {:>9.2} {:>9.2} {:>9.1} {:>9.1}", led, lo, hi, lo * bin_width, hi * bin_width);
     }
 }
 
 // FFT processing.
 fn fft_thread(
     audio_reciever: channel::Receiver<(f32, f32)>,
-    led_sender: channel::Sender<f32>,
-    power_sender: channel::Sender<f32>,
+    frame_sender: channel::Sender<Frame>,
+    mut pipeline: Pipeline,
+    dc_block: process::DcBlock,
+    mut vu_scale: process::DbScale,
     args: Args,
 ) {
     let fft_size = args.fft_size;
@@ -142,7 +927,7 @@ fn fft_thread(
     let mut fft_data = vec![Complex::default(); fft_size];
     let mut f32_scratch = vec![0.0; fft_size];
     let mut agc_scratch = vec![0.0; fft_size];
-    let mut leds = vec![0.0; NUM_LEDS];
+    let mut peaks = vec![0.0; NUM_LEDS];
 
     // Only interested in the first half since the is real data, and because
     // nyquist is a problem.
@@ -150,32 +935,52 @@ fn fft_thread(
 
     // Calculate the overlap to faciliate a pseudo-welch's method.
     let overlap = (fft_size as f32 * args.overlap) as usize;
+    let frame_rate = frame_rate(&args);
 
-    // Setup the principal decay engine.
-    let mut fft_decay = process::ExpDecay::new(
-        fft_nyquist,
-        44100.0 / fft_nyquist as f32,
-        args.decay_time,
-        1.0,
-    );
-
-    let fft_high_bin = f_to_bin(fft_size, 44100.0, args.mf);
-
-    //let agc_sections = args.agc_sections * (fft_nyquist / fft_high_bin);
-    //println!("{}", agc_sections);
-    //let agc_chunk_size = (fft_nyquist + agc_sections - 1) / agc_sections;
-    let mut agc = process::AGC::new(args.agc_len, args.agc_target, args.lin_cor);
-    
     let mut pre_agc: Box<dyn ProcessMut<_,_>> = if args.preagc {
-        Box::new(process::TimeAGC::new(args.agc_len, 1.0))
+        let tracker = process::LevelTracker::new(frame_rate, args.agc_rise, args.agc_fall, 1.0);
+        Box::new(process::TimeAGC::new(tracker, 1.0, db_to_gain(args.agc_max_gain)))
     } else {
         Box::new(process::PassThrough)
     };
-    //let mut agcs : Vec<process::AGC> = std::iter::repeat_with(|| process::AGC::new(args.agc_len, args.agc_target, 1))
-        // .take(agc_sections)
-        // .collect();
-    // Setup the Frequency -> LED mapper.
-    let led_map = process::PageLog::new(fft_size, 44100.0, args.mf, NUM_LEDS);
+
+    let mut peak_hold = process::PeakHold::new(NUM_LEDS, frame_rate, args.peak_hold, args.peak_gravity);
+
+    // Onsets are picked out of the raw spectrum, so AGC gain changes don't register as hits.
+    let beat_hi = f_to_bin(fft_size, 44100.0, args.mf).min(fft_nyquist);
+    let beat_bins = f_to_bin(fft_size, 44100.0, args.min_freq).min(beat_hi)..beat_hi;
+    let mut onsets = beat::OnsetDetector::new(beat_bins, frame_rate, args.beat_window, args.beat_sensitivity);
+
+    // Each band gets its own power meter, and its own onset detector to flash on its hits.
+    let band_names: Vec<String> = band_specs(&args).into_iter().map(|b| b.name).collect();
+    let mut bands: Vec<_> = band_specs(&args).iter().map(|band| {
+        let power = process::BandPower::new(fft_size, 44100.0, band.lo, band.hi);
+        let onsets = beat::OnsetDetector::new(power.bins(), frame_rate, args.beat_window, args.beat_sensitivity);
+        let flash = beat::BeatFlash::new(frame_rate, args.beat_decay);
+        (power, onsets, flash)
+    }).collect();
+    let mut band_levels = vec![0.0; bands.len()];
+    let mut tempo_tracker = beat::TempoTracker::new(frame_rate, 8.0);
+    let mut beat_flash = beat::BeatFlash::new(frame_rate, args.beat_decay);
+    let mut frame_count = 0;
+
+    // Gate out residual noise ahead of the AGCs, so it isn't scaled up towards the target.
+    let mut noise_gate = process::NoiseGate::new(
+        frame_rate,
+        args.gate_threshold,
+        args.gate_hysteresis,
+        args.gate_hold,
+    );
+
+    // Strip any DC offset from the capture device before it reaches the FFT.
+    let mut dc_left = dc_block.clone();
+    let mut dc_right = dc_block;
+
+    // Per channel RMS of the samples taken in each frame, with VU style ballistics.
+    let mut vu_rms = [0.0; 2];
+    let mut vu_level = [0.0; 2];
+    let mut vu = [0.0; 2];
+    let mut vu_envelope = process::Envelope::new(2, frame_rate, 0.0, 0.3, process::Detector::Peak, f32::MAX);
 
     loop {
         // Create a running buffer, dropping and consuming `overlap` amounts of data each time, except for initial fill.
@@ -183,13 +988,26 @@ fn fft_thread(
         let drain_amount = std::cmp::min(sample_vec.len(), overlap);
         sample_vec.drain(..drain_amount);
 
+        let wanted = fft_size - sample_vec.len();
+        let mut channel_power = [0.0; 2];
         let sample_iterator = audio_reciever
             .iter()
-            .take(fft_size - sample_vec.len())
-            .map(|(l, r)| (l + r) / 2.0);
-        
+            .take(wanted)
+            .map(|(l, r)| {
+                let (l, r) = (dc_left.filter(l), dc_right.filter(r));
+                channel_power[0] += l * l;
+                channel_power[1] += r * r;
+                (l + r) / 2.0
+            });
+
         sample_vec.extend(sample_iterator);
 
+        for (rms, power) in vu_rms.iter_mut().zip(channel_power.iter()) {
+            *rms = (power / wanted.max(1) as f32).sqrt();
+        }
+        vu_envelope.process(&vu_rms, &mut vu_level, 1.0);
+        vu_scale.process(&vu_level, &mut vu, 1.0);
+
         // Copy into a continous buffer since a dequeue is represented as two slices.
         let (sample_left, sample_right) = sample_vec.as_slices();
         let left_len = sample_left.len();
@@ -197,14 +1015,11 @@ fn fft_thread(
         scratch_l.copy_from_slice(sample_left);
         scratch_r.copy_from_slice(sample_right);
 
-        // for (section, (in_chunk, out_chunk)) in f32_scratch.chunks(agc_chunk_size).zip(agc_scratch.chunks_mut(agc_chunk_size)).enumerate() {
-        //     agcs[section].process(&in_chunk[..], &mut out_chunk[..], 0.0);
-        // }
-
-        pre_agc.process(&f32_scratch[..], &mut agc_scratch[..], 0.0);
+        noise_gate.process(&f32_scratch[..], &mut agc_scratch[..], 0.0);
+        pre_agc.process(&agc_scratch[..], &mut f32_scratch[..], 0.0);
 
         // Window the data to prevent spectral contamination, then compute the FFT.
-        NutallWindow.window(&agc_scratch[..], &mut windowed_samples[..]);
+        NutallWindow.window(&f32_scratch[..], &mut windowed_samples[..]);
         fft.process(&mut windowed_samples[..], &mut fft_data[..]);
 
         for (c, a) in fft_data.iter().zip(fft_energy.iter_mut()) {
@@ -213,47 +1028,43 @@ fn fft_thread(
             *a = norm;
         }
 
-        // for (section, (in_chunk, out_chunk)) in fft_energy.chunks(agc_chunk_size).zip(f32_scratch.chunks_mut(agc_chunk_size)).enumerate() {
-        //     agcs[section].process(&in_chunk[..], &mut out_chunk[..], 0.0);
-        // }
+        // Run the spectrum through the pipeline down to one value per LED.
+        pipeline.process(&fft_energy[..]);
 
-        agc.process(&fft_energy[..], &mut f32_scratch[..], 0.0);
+        let flash = beat_flash.update(onsets.detect(&fft_energy[..]));
+        let tempo = tempo_tracker.update(onsets.flux());
 
-        for (c, a) in f32_scratch.iter().zip(fft_energy.iter_mut()) {
-            // powf
-            let pow = c.powf(args.exp);
-            *a = pow;
+        // The boom segment is driven either by beats, or by the processed spectrum as it enters the mapper.
+        let spectrum = pipeline.input_of("map").unwrap_or(&fft_energy[..]);
+        for ((power, onsets, flash), level) in bands.iter_mut().zip(band_levels.iter_mut()) {
+            let flash = flash.update(onsets.detect(&fft_energy[..]));
+            *level = if args.beats { flash } else { power.power(spectrum) };
         }
 
-        // Process the decay, then calculate the log magnitude from there.
-        fft_decay.process(&mut fft_energy[..], &mut f32_scratch[..], 1.0);
-
-        // Floor the function if nessiary, then apply a postscaler.
-        for (&i, o) in f32_scratch.iter().zip(fft_energy.iter_mut()) {
-            if i < 0.001 {
-                *o = 0.0;
-            } else {
-                //*o = args.fftscale * i.sqrt();
-                *o = i;
-            }
+        frame_count += 1;
+        if args.stats && frame_count % (frame_rate as usize).max(1) == 0 {
+            let (bpm, phase) = tempo.map_or(("--".to_string(), "--".to_string()), |t| {
+                (format!("{:.1}", t.bpm), format!("{:.2}", t.phase))
+            });
+            let levels: Vec<String> = band_names.iter().zip(band_levels.iter())
+                .map(|(name, level)| format!("{}: {:.2}", name, level))
+                .collect();
+            println!("BPM: {} phase: {} {}", bpm, phase, levels.join(" "));
         }
-        
-        let power_value = if args.boom_count > 0 {
-            fft_energy[0..args.boom_count].iter().fold(0.0, |a, e| a + e) / args.boom_count as f32
-        } else {
-            0.0
-        };
 
-        let power_data = std::iter::repeat(power_value).take(50);
-        for x in power_data {
-            let _ = power_sender.try_send(x);
-        }
+        // Send to the main thread for display.
+        let leds = pipeline.output();
+        peak_hold.process(leds, &mut peaks[..], 1.0);
 
-        // Map to leds, and send to the main thread for display.
-        led_map.process(&fft_energy[..], &mut leds[..], 1.0);
-        for &l in &leds {
-            let _ = led_sender.try_send(l);
-        }
+        let _ = frame_sender.try_send(Frame {
+            leds: leds.to_vec(),
+            peaks: peaks.clone(),
+            bands: band_levels.clone(),
+            gated: !noise_gate.is_open(),
+            tempo,
+            flash,
+            vu,
+        });
     }
 }
 
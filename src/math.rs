@@ -45,3 +45,127 @@ pub fn f_to_bin(fft_size: usize, fs: f32, f: f32) -> usize {
     (f * (fft_size as f32 / fs)) as usize
 }
 
+// Spectral tilt / EQ curve. `tilt` is in dB per octave around 1 kHz, `points` are
+// (frequency, dB) pairs interpolated linearly in log frequency and held flat past either end.
+#[derive(Debug, Clone, Default)]
+pub struct EqCurve {
+    tilt: f32,
+    points: Vec<(f32, f32)>
+}
+
+impl EqCurve {
+    // `points` may come in any order, but must all be finite.
+    pub fn new(tilt: f32, points: &[(f32, f32)]) -> Self {
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        EqCurve {
+            tilt,
+            points
+        }
+    }
+
+    pub fn gain_db(&self, f: f32) -> f32 {
+        // DC has no octave, treat it as the bottom of the audible range.
+        let octave = f.max(20.0).log2();
+        let mut gain = self.tilt * (octave - 1000f32.log2());
+
+        let points = &self.points;
+        if let (Some(first), Some(last)) = (points.first(), points.last()) {
+            gain += if f <= first.0 {
+                first.1
+            } else if f >= last.0 {
+                last.1
+            } else {
+                let (lo, hi) = points.windows(2)
+                    .map(|w| (w[0], w[1]))
+                    .find(|(_, hi)| f < hi.0)
+                    .unwrap();
+                let t = (octave - lo.0.log2()) / (hi.0.log2() - lo.0.log2());
+                lo.1 + (hi.1 - lo.1) * t
+            };
+        }
+
+        gain
+    }
+
+    // Linear gain for each of the first `num_bins` FFT bins.
+    pub fn bin_gains(&self, fft_size: usize, fs: f32, num_bins: usize) -> Vec<f32> {
+        (0..num_bins)
+            .map(|bin| 10f32.powf(self.gain_db(bin as f32 * fs / fft_size as f32) / 20.0))
+            .collect()
+    }
+}
+
+// Fractional bin position of a frequency, used by the filterbank mappers.
+pub fn f_to_bin_f(fft_size: usize, fs: f32, f: f32) -> f32 {
+    f * (fft_size as f32 / fs)
+}
+
+pub fn hz_to_mel(f: f32) -> f32 {
+    2595.0 * (1.0 + f / 700.0).log10()
+}
+
+pub fn mel_to_hz(m: f32) -> f32 {
+    700.0 * (10f32.powf(m / 2595.0) - 1.0)
+}
+
+// Traunmüller's approximation of the Bark scale, which has a closed form inverse.
+pub fn hz_to_bark(f: f32) -> f32 {
+    (26.81 * f) / (1960.0 + f) - 0.53
+}
+
+pub fn bark_to_hz(z: f32) -> f32 {
+    1960.0 * (z + 0.53) / (26.28 - z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn eq_interpolates_in_log_frequency() {
+        let eq = EqCurve::new(0.0, &[(100.0, 0.0), (400.0, 12.0)]);
+        assert!(close(eq.gain_db(100.0), 0.0));
+        assert!(close(eq.gain_db(400.0), 12.0));
+        // 200 Hz is one octave of the two, so halfway in log frequency.
+        assert!(close(eq.gain_db(200.0), 6.0));
+    }
+
+    #[test]
+    fn eq_holds_flat_past_the_ends() {
+        let eq = EqCurve::new(0.0, &[(100.0, -3.0), (1000.0, 6.0)]);
+        assert!(close(eq.gain_db(20.0), -3.0));
+        assert!(close(eq.gain_db(0.0), -3.0));
+        assert!(close(eq.gain_db(15000.0), 6.0));
+    }
+
+    #[test]
+    fn eq_sorts_points() {
+        let eq = EqCurve::new(0.0, &[(400.0, 12.0), (100.0, 0.0)]);
+        assert!(close(eq.gain_db(200.0), 6.0));
+    }
+
+    #[test]
+    fn eq_tilt_pivots_on_1khz() {
+        let eq = EqCurve::new(3.0, &[]);
+        assert!(close(eq.gain_db(1000.0), 0.0));
+        assert!(close(eq.gain_db(2000.0), 3.0));
+        assert!(close(eq.gain_db(250.0), -6.0));
+    }
+
+    #[test]
+    fn eq_bin_gains_are_linear() {
+        let eq = EqCurve::new(0.0, &[(1000.0, 20.0)]);
+        let gains = eq.bin_gains(1024, 44100.0, 4);
+        assert_eq!(gains.len(), 4);
+        assert!(gains.iter().all(|&g| close(g, 10.0)));
+
+        let flat = EqCurve::default().bin_gains(1024, 44100.0, 8);
+        assert!(flat.iter().all(|&g| close(g, 1.0)));
+    }
+}
\ No newline at end of file
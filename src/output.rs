@@ -0,0 +1,161 @@
+use super::color::{RGB, RGBf};
+
+// Resolution of the gamma tables, well past 8 bits so dithering has something to work with.
+const LUT_SIZE: usize = 4096;
+
+// Corrections applied to every LED on its way out to the strip. The calibration matrix mixes
+// and scales the channels to white balance the strip, then a per channel gamma lookup table
+// compensates for the LEDs' nonlinear response.
+pub struct Calibration {
+    matrix: [[f32; 3]; 3],
+    lut: [Vec<f32>; 3],
+}
+
+impl Calibration {
+    pub fn new(matrix: [[f32; 3]; 3], gamma: [f32; 3]) -> Self {
+        let table = |gamma: f32| (0..LUT_SIZE)
+            .map(|i| 255.0 * (i as f32 / (LUT_SIZE - 1) as f32).powf(gamma))
+            .collect();
+
+        Calibration {
+            matrix,
+            lut: [table(gamma[0]), table(gamma[1]), table(gamma[2])],
+        }
+    }
+
+    pub fn apply(&self, c: RGBf) -> RGBf {
+        let input = [c.r, c.g, c.b];
+        let mut out = [0.0; 3];
+        for (channel, (row, table)) in out.iter_mut().zip(self.matrix.iter().zip(self.lut.iter())) {
+            let mixed = row.iter().zip(input.iter()).map(|(m, i)| m * i).sum::<f32>();
+            let idx = f32::min(mixed / 255.0, 1.0).max(0.0) * (LUT_SIZE - 1) as f32;
+            *channel = table[idx.round() as usize];
+        }
+
+        RGBf {
+            r: out[0],
+            g: out[1],
+            b: out[2],
+        }
+    }
+}
+
+// Temporal error diffusion down to 8 bits. The rounding error of each LED channel is carried
+// into the next frame, so a level between two steps alternates between them and averages out
+// to the right brightness at our refresh rate.
+pub struct Dither {
+    error: Vec<[f32; 3]>,
+    enabled: bool,
+}
+
+impl Dither {
+    pub fn new(num_leds: usize, enabled: bool) -> Self {
+        Dither {
+            error: vec![[0.0; 3]; num_leds],
+            enabled,
+        }
+    }
+
+    pub fn quantize(&mut self, led: usize, c: RGBf) -> RGB {
+        if !self.enabled {
+            return RGB::new(c.r as u8, c.g as u8, c.b as u8);
+        }
+
+        let error = &mut self.error[led];
+        let mut out = [0; 3];
+        for ((o, e), &v) in out.iter_mut().zip(error.iter_mut()).zip([c.r, c.g, c.b].iter()) {
+            let target = v + *e;
+            let q = f32::min(target.round(), 255.0).max(0.0);
+            *e = target - q;
+            *o = q as u8;
+        }
+
+        RGB::new(out[0], out[1], out[2])
+    }
+}
+
+// Keeps the strip inside what the supply can deliver. Every channel is assumed to draw
+// current in proportion to its PWM duty, `ma_per_channel` at full on, so the estimate has to be
+// made on the calibrated values that actually go out.
+pub struct PowerLimit {
+    max_brightness: f32,
+    ma_per_channel: [f32; 3],
+    // Total current budget in mA, 0 for no limit.
+    budget: f32,
+}
+
+impl PowerLimit {
+    pub fn new(max_brightness: f32, ma_per_channel: [f32; 3], budget: f32) -> Self {
+        PowerLimit {
+            max_brightness: f32::min(max_brightness, 1.0).max(0.0),
+            ma_per_channel,
+            budget,
+        }
+    }
+
+    // Estimated draw in mA of the given LEDs.
+    pub fn current(&self, leds: &[RGBf]) -> f32 {
+        let [r, g, b] = self.ma_per_channel;
+        leds.iter().map(|c| (c.r * r + c.g * g + c.b * b) / 255.0).sum()
+    }
+
+    // Scales the frame by the brightness limit, then further down if it would still pull more
+    // than the budget.
+    pub fn apply(&self, leds: &mut [RGBf]) {
+        let mut scale = self.max_brightness;
+        let current = scale * self.current(leds);
+        if self.budget > 0.0 && current > self.budget {
+            scale *= self.budget / current;
+        }
+
+        if scale < 1.0 {
+            for c in leds.iter_mut() {
+                *c = scale * *c;
+            }
+        }
+    }
+}
+
+// Everything between the rendered frame and the bytes on the wire.
+pub struct Output {
+    calibration: Calibration,
+    limit: PowerLimit,
+    dither: Dither,
+    wire: Vec<RGBf>,
+}
+
+impl Output {
+    pub fn new(calibration: Calibration, limit: PowerLimit, dither: Dither) -> Self {
+        Output {
+            calibration,
+            limit,
+            dither,
+            wire: Vec::new(),
+        }
+    }
+
+    // Appends the LEDs, in wire order, to `buf`.
+    pub fn encode<'a, I: Iterator<Item = &'a RGBf>>(&mut self, leds: I, buf: &mut Vec<u8>) {
+        self.wire.clear();
+        let calibration = &self.calibration;
+        self.wire.extend(leds.map(|&c| calibration.apply(c)));
+        self.limit.apply(&mut self.wire);
+        for (led, &c) in self.wire.iter().enumerate() {
+            buf.extend_from_slice(self.dither.quantize(led, c).as_slice());
+        }
+    }
+}
+
+// Calibration test pattern, `t` seconds in and `position` 0..1 along the strip. Holds solid red,
+// green, blue and white for two seconds each to check the white balance, then a gray ramp along
+// the strip to check the gamma, which should look like an even fade.
+pub fn test_pattern(t: f32, position: f32) -> RGBf {
+    let c = match (t / 2.0) as u32 % 5 {
+        0 => RGB::new(255, 0, 0),
+        1 => RGB::new(0, 255, 0),
+        2 => RGB::new(0, 0, 255),
+        3 => RGB::new(255, 255, 255),
+        _ => return position * RGBf::from(RGB::new(255, 255, 255)),
+    };
+    c.into()
+}
@@ -0,0 +1,123 @@
+use super::color::{self, ColorSpace, RGB};
+
+// Multi-stop color gradient, interpolated in OKLab unless told otherwise.
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    stops: Vec<(f32, RGB)>,
+    space: ColorSpace,
+}
+
+impl Gradient {
+    // `stops` are (position, color) pairs with positions in 0..1.
+    pub fn new(stops: &[(f32, RGB)]) -> Self {
+        let mut stops = stops.to_vec();
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        Gradient {
+            stops,
+            space: ColorSpace::Oklab,
+        }
+    }
+
+    pub fn in_space(self, space: ColorSpace) -> Self {
+        Gradient { space, ..self }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        let stops: &[(f32, u32)] = match name {
+            // Bass red through to treble blue.
+            "spectrum" => &[(0.0, 0xFF0000), (0.5, 0x00FF00), (1.0, 0x0000FF)],
+            // Cool to hot.
+            "heat" => &[(0.0, 0x0000FF), (0.33, 0x00FFFF), (0.66, 0xFFFF00), (1.0, 0xFF0000)],
+            "fire" => &[(0.0, 0xFF0000), (0.5, 0xFF8000), (1.0, 0xFFFF00)],
+            "ocean" => &[(0.0, 0x0000FF), (0.5, 0x0080FF), (1.0, 0x00FFC0)],
+            _ => return None,
+        };
+
+        let stops: Vec<(f32, RGB)> = stops.iter()
+            .map(|&(t, hex)| (t, RGB::from_u32(hex)))
+            .collect();
+        Some(Gradient::new(&stops))
+    }
+
+    pub fn at(&self, t: f32) -> RGB {
+        let first = self.stops[0];
+        let last = self.stops[self.stops.len() - 1];
+
+        if t <= first.0 {
+            first.1
+        } else if t >= last.0 {
+            last.1
+        } else {
+            let w = self.stops.windows(2).find(|w| t < w[1].0).unwrap();
+            let (lo, hi) = (w[0], w[1]);
+            self.space.lerp(lo.1, hi.1, (t - lo.0) / (hi.0 - lo.0))
+        }
+    }
+}
+
+// Either the name of a built-in palette, or comma separated stops. Stops are `pos:color`, or
+// a bare color to space them evenly.
+impl std::str::FromStr for Gradient {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(gradient) = Gradient::builtin(s) {
+            return Ok(gradient);
+        }
+
+        let fields = color::split_list(s);
+        if fields.len() < 2 {
+            return Err(format!("palette `{}` is not built-in (spectrum, heat, fire, ocean) and needs at least two stops", s));
+        }
+
+        let parse_color = |c: &str| c.parse::<RGB>().map_err(|e| format!("bad palette color: {}", e));
+
+        let stops = fields.iter().enumerate().map(|(idx, field)| {
+            match field.find(':') {
+                Some(split) => {
+                    let pos: f32 = field[..split].parse().map_err(|e| format!("bad palette position `{}`: {}", &field[..split], e))?;
+                    if !(0.0..=1.0).contains(&pos) {
+                        return Err(format!("palette position `{}` should be between 0 and 1", &field[..split]));
+                    }
+                    Ok((pos, parse_color(&field[split + 1..])?))
+                }
+                None => Ok((idx as f32 / (fields.len() - 1) as f32, parse_color(field)?)),
+            }
+        }).collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Gradient::new(&stops))
+    }
+}
+
+// What a palette is keyed on.
+#[derive(Debug, Clone, Copy)]
+pub enum PaletteMode {
+    // Position along the strip, from the lowest to the highest frequency.
+    Position,
+    // The LED's intensity.
+    Intensity,
+    // The average of the two.
+    Both,
+}
+
+impl PaletteMode {
+    pub fn key(self, position: f32, intensity: f32) -> f32 {
+        match self {
+            PaletteMode::Position => position,
+            PaletteMode::Intensity => intensity,
+            PaletteMode::Both => (position + intensity) / 2.0,
+        }
+    }
+}
+
+impl std::str::FromStr for PaletteMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "position" => Ok(PaletteMode::Position),
+            "intensity" => Ok(PaletteMode::Intensity),
+            "both" => Ok(PaletteMode::Both),
+            _ => Err(format!("unknown palette mode `{}`, expected one of: position, intensity, both", s)),
+        }
+    }
+}
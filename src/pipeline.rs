@@ -0,0 +1,72 @@
+use super::process::ProcessMut;
+
+// A named step of a `Pipeline`, owning the buffer it writes its output into.
+pub struct Stage {
+    name: String,
+    process: Box<dyn ProcessMut<f32, f32> + Send>,
+    output: Vec<f32>,
+}
+
+impl Stage {
+    // `output_len` is how many values the stage produces, which may differ from its input,
+    // e.g. the frequency -> LED mapper.
+    pub fn new(name: &str, process: Box<dyn ProcessMut<f32, f32> + Send>, output_len: usize) -> Self {
+        Stage {
+            name: name.to_string(),
+            process,
+            output: vec![0.0; output_len],
+        }
+    }
+}
+
+// Chains `ProcessMut` stages, feeding each stage's output into the next. The intermediate
+// buffers are kept around so other consumers can tap the signal between stages.
+pub struct Pipeline {
+    stages: Vec<Stage>,
+    input_len: usize,
+}
+
+impl Pipeline {
+    pub fn new(input_len: usize) -> Self {
+        Pipeline {
+            stages: Vec::new(),
+            input_len,
+        }
+    }
+
+    pub fn push(&mut self, stage: Stage) -> &mut Self {
+        self.stages.push(stage);
+        self
+    }
+
+    // Length of the signal coming out of the last stage, which is what the next pushed stage sees.
+    pub fn output_len(&self) -> usize {
+        self.stages.last().map_or(self.input_len, |s| s.output.len())
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> &[f32] {
+        for idx in 0..self.stages.len() {
+            let (done, rest) = self.stages.split_at_mut(idx);
+            let sig = done.last().map_or(input, |s| &s.output[..]);
+            let stage = &mut rest[0];
+            stage.process.process(sig, &mut stage.output[..], 1.0);
+        }
+
+        self.output()
+    }
+
+    pub fn output(&self) -> &[f32] {
+        self.stages.last().map_or(&[], |s| &s.output[..])
+    }
+
+    // The signal fed into the stage called `name`, as of the last `process` call.
+    // Returns `None` for the first stage, since its input isn't retained.
+    pub fn input_of(&self, name: &str) -> Option<&[f32]> {
+        let idx = self.stages.iter().position(|s| s.name == name)?;
+        if idx == 0 {
+            None
+        } else {
+            Some(&self.stages[idx - 1].output[..])
+        }
+    }
+}
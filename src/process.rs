@@ -1,5 +1,4 @@
 use super::math::*;
-use std::collections::VecDeque;
 
 pub trait Process<T, U> {
     fn process(&self, sig: &[T], output: &mut[U], p: f32);
@@ -9,47 +8,227 @@ pub trait ProcessMut<T, U> {
     fn process(&mut self, sig: &[T], output: &mut[U], p: f32);
 }
 
+// A frequency -> LED mapper whose bin table can be inspected, see `--print-map`.
+pub trait LedMap: Process<f32, f32> {
+    // The (low, high) fractional FFT bins each LED covers.
+    fn bin_table(&self) -> Vec<(f32, f32)>;
+}
+
+// Checks the range a map is to cover before its edges are laid out.
+fn check_range(sample_rate: f32, min_frequency: f32, max_frequency: f32) -> Result<(), String> {
+    if !(min_frequency.is_finite() && max_frequency.is_finite()) || min_frequency < 0.0 {
+        return Err(format!("[{}, {}] Hz is not a valid frequency range", min_frequency, max_frequency));
+    }
+    if min_frequency >= max_frequency {
+        return Err(format!("min frequency {} is not below max frequency {}", min_frequency, max_frequency));
+    }
+    if max_frequency > sample_rate / 2.0 {
+        return Err(format!("max frequency {} is above nyquist at {}", max_frequency, sample_rate / 2.0));
+    }
+    Ok(())
+}
+
 pub struct PageLog {
-    led_bin_map: Vec<usize>
+    led_edges: Vec<f32>
 }
 
 impl PageLog {
-    // Shifted rounding scheme to reduce smearing
-    pub fn new(fft_size: usize, sample_rate: f32, max_frequency: f32, num_leds: usize) -> Self {
-        let base = f_to_bin(fft_size, sample_rate, max_frequency) as f32;
-
-        let bins = (0..num_leds).map(|idx| {
-            let b1 = f32::min(base.powf(idx as f32 / ((num_leds as f32) - 1.0)), (fft_size as f32/ 2.0) - 1.0);
-            let b1 = (b1 + (idx as f32)).round() as usize;
-            b1
+    // Shifted rounding scheme to reduce smearing. Each edge is pushed up by its index so
+    // the low LEDs don't pile onto the same bins, and the log base is shrunk by the total shift
+    // so the last edge still lands on `max_frequency`. For small FFTs where there is no room
+    // for a whole bin of shift per LED this degrades into a linear map.
+    pub fn new(fft_size: usize, sample_rate: f32, min_frequency: f32, max_frequency: f32, num_leds: usize) -> Result<Self, String> {
+        check_range(sample_rate, min_frequency, max_frequency)?;
+
+        let fft_nyquist = fft_size / 2;
+        let n = num_leds as f32;
+        let high = f32::min(f_to_bin(fft_size, sample_rate, max_frequency) as f32, (fft_nyquist - 1) as f32);
+        let low = f32::min(f_to_bin_f(fft_size, sample_rate, min_frequency).max(0.0), high);
+        let span = high - low;
+
+        let shift = f32::min(1.0, span / n);
+        let base = span + 1.0 - n * shift;
+
+        let led_edges = (0..=num_leds).map(|idx| {
+            let e = low + base.powf(idx as f32 / n) - 1.0 + idx as f32 * shift;
+            f32::min(e, high)
         }).collect();
 
-        PageLog {
-            led_bin_map: bins
-        }
+        let map = PageLog {
+            led_edges
+        };
+        map.validate(fft_nyquist)?;
+        Ok(map)
     }
 
-    pub fn get_bin(&self, led: usize) -> usize
-    {
-        self.led_bin_map[led]
-    } 
+    fn validate(&self, fft_nyquist: usize) -> Result<(), String> {
+        if self.led_edges.len() < 2 {
+            return Err("LED map needs at least one LED".to_string());
+        }
+
+        for (led, w) in self.led_edges.windows(2).enumerate() {
+            if !(w[0].is_finite() && w[1].is_finite()) {
+                return Err(format!("LED {} has a non-finite bin edge", led));
+            }
+            if w[1] < w[0] {
+                return Err(format!("LED {} bins are not monotonic: {} > {}", led, w[0], w[1]));
+            }
+        }
+
+        let (first, last) = (self.led_edges[0], self.led_edges[self.led_edges.len() - 1]);
+        if first < 0.0 || last > (fft_nyquist - 1) as f32 {
+            return Err(format!("LED map [{}, {}] exceeds the FFT bins [0, {}]", first, last, fft_nyquist - 1));
+        }
+
+        Ok(())
+    }
 }
 
 impl Process<f32, f32> for PageLog {
     fn process(&self, sig: &[f32], output: &mut[f32], _: f32) {
-        let mut b0 = 0;
-        for (&bin, o) in self.led_bin_map.iter().zip(output.iter_mut()) {
-            let diff = usize::max(bin-b0, 1);
-            let val = sig.iter().skip(b0).take(diff).fold(0.0, |acc, &x| return acc + x) / diff as f32;
+        for (w, o) in self.led_edges.windows(2).zip(output.iter_mut()) {
+            let (lo, hi) = (w[0].round() as usize, w[1].round() as usize);
+
+            let val = if hi > lo {
+                sig[lo..hi].iter().sum::<f32>() / (hi - lo) as f32
+            } else {
+                // Several LEDs share this bin, interpolate between its neighbours
+                // instead of repeating the same value.
+                let center = (w[0] + w[1]) / 2.0;
+                let b0 = usize::min(center.floor() as usize, sig.len() - 2);
+                let frac = center - b0 as f32;
+                sig[b0] * (1.0 - frac) + sig[b0 + 1] * frac
+            };
+
+            *o = f32::min(val, 1.0);
+        }
+    }
+}
+
+impl LedMap for PageLog {
+    fn bin_table(&self) -> Vec<(f32, f32)> {
+        self.led_edges.windows(2).map(|w| (w[0], w[1])).collect()
+    }
+}
+
+// Triangular filterbank over the FFT bins. Each LED gets a triangle spanning
+// its neighbours' center frequencies, with weights normalized to sum to one.
+struct TriangleBank {
+    filters: Vec<(usize, Vec<f32>)>,
+    corners: Vec<(f32, f32)>
+}
 
-            //let val_norm = (f32::min(val / scale, 1.0) * 255.0) as u8;
-            let val_norm = f32::min(val, 1.0);
-            *o = val_norm;
-            b0 = bin;
+impl TriangleBank {
+    // `edges` are the frequencies of the filter corners, `num_leds + 2` of them.
+    fn new(fft_size: usize, sample_rate: f32, edges: &[f32]) -> Self {
+        let fft_nyquist = fft_size / 2;
+        let edges: Vec<f32> = edges.iter()
+            .map(|&f| f32::min(f_to_bin_f(fft_size, sample_rate, f), (fft_nyquist - 1) as f32))
+            .collect();
+
+        let corners = edges.windows(3).map(|w| (w[0], w[2])).collect();
+        let filters = edges.windows(3).map(|w| {
+            let (lo, center, hi) = (w[0], w[1], w[2]);
+            let start = lo.ceil() as usize;
+            let mut weights: Vec<f32> = (start..=hi.floor() as usize).map(|k| {
+                let k = k as f32;
+                if k <= center {
+                    if center > lo { (k - lo) / (center - lo) } else { 1.0 }
+                } else {
+                    (hi - k) / (hi - center)
+                }
+            }).collect();
+
+            let sum: f32 = weights.iter().sum();
+            if sum > 0.0 {
+                weights.iter_mut().for_each(|w| *w /= sum);
+                (start, weights)
+            } else {
+                // The triangle is narrower than a bin, so interpolate at its center instead.
+                let base = usize::min(center.floor() as usize, fft_nyquist - 2);
+                let frac = center - base as f32;
+                (base, vec![1.0 - frac, frac])
+            }
+        }).collect();
+
+        TriangleBank {
+            filters,
+            corners
         }
     }
 }
 
+impl Process<f32, f32> for TriangleBank {
+    fn process(&self, sig: &[f32], output: &mut[f32], _: f32) {
+        for ((start, weights), o) in self.filters.iter().zip(output.iter_mut()) {
+            let val = sig.iter().skip(*start).zip(weights.iter()).fold(0.0, |acc, (&x, &w)| acc + x * w);
+            *o = f32::min(val, 1.0);
+        }
+    }
+}
+
+pub struct MelMap {
+    bank: TriangleBank
+}
+
+impl MelMap {
+    pub fn new(fft_size: usize, sample_rate: f32, min_frequency: f32, max_frequency: f32, num_leds: usize) -> Result<Self, String> {
+        check_range(sample_rate, min_frequency, max_frequency)?;
+        let min_mel = hz_to_mel(min_frequency);
+        let max_mel = hz_to_mel(max_frequency);
+        let edges: Vec<f32> = (0..num_leds + 2)
+            .map(|idx| mel_to_hz(min_mel + (max_mel - min_mel) * idx as f32 / (num_leds + 1) as f32))
+            .collect();
+
+        Ok(MelMap {
+            bank: TriangleBank::new(fft_size, sample_rate, &edges)
+        })
+    }
+}
+
+impl Process<f32, f32> for MelMap {
+    fn process(&self, sig: &[f32], output: &mut[f32], p: f32) {
+        self.bank.process(sig, output, p);
+    }
+}
+
+impl LedMap for MelMap {
+    fn bin_table(&self) -> Vec<(f32, f32)> {
+        self.bank.corners.clone()
+    }
+}
+
+pub struct BarkMap {
+    bank: TriangleBank
+}
+
+impl BarkMap {
+    pub fn new(fft_size: usize, sample_rate: f32, min_frequency: f32, max_frequency: f32, num_leds: usize) -> Result<Self, String> {
+        check_range(sample_rate, min_frequency, max_frequency)?;
+        let min_bark = hz_to_bark(min_frequency);
+        let max_bark = hz_to_bark(max_frequency);
+        let edges: Vec<f32> = (0..num_leds + 2)
+            .map(|idx| bark_to_hz(min_bark + (max_bark - min_bark) * idx as f32 / (num_leds + 1) as f32))
+            .collect();
+
+        Ok(BarkMap {
+            bank: TriangleBank::new(fft_size, sample_rate, &edges)
+        })
+    }
+}
+
+impl Process<f32, f32> for BarkMap {
+    fn process(&self, sig: &[f32], output: &mut[f32], p: f32) {
+        self.bank.process(sig, output, p);
+    }
+}
+
+impl LedMap for BarkMap {
+    fn bin_table(&self) -> Vec<(f32, f32)> {
+        self.bank.corners.clone()
+    }
+}
+
 // pub struct PageLog {
 //     led_bin_map: Vec<usize>
 // }
@@ -92,97 +271,390 @@ impl Process<f32, f32> for PageLog {
 //     }
 // }
 
-pub struct ExpDecay {
+// How the envelope follower measures level.
+#[derive(Debug, Clone, Copy)]
+pub enum Detector {
+    Peak,
+    Rms
+}
+
+impl std::str::FromStr for Detector {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "peak" => Ok(Detector::Peak),
+            "rms" => Ok(Detector::Rms),
+            _ => Err(format!("unknown envelope `{}`, expected one of: peak, rms", s)),
+        }
+    }
+}
+
+// Attack/release envelope follower. Rising input is tracked with the attack time constant and
+// falling input with the release one, both in seconds, a time of zero follows instantly.
+pub struct Envelope {
     memory: Vec<f32>,
-    decay_rate: f32,
+    attack_rate: f32,
+    release_rate: f32,
+    detector: Detector,
     max: f32
 }
 
-impl ExpDecay {
-    pub fn new(cap: usize, sample_rate: f32, decay_time: f32, max: f32) -> Self {
-        ExpDecay {
+fn time_constant(frame_rate: f32, time: f32) -> f32 {
+    if time > 0.0 {
+        (1.0 / (- time * frame_rate)).exp()
+    } else {
+        0.0
+    }
+}
+
+impl Envelope {
+    pub fn new(cap: usize, frame_rate: f32, attack_time: f32, release_time: f32, detector: Detector, max: f32) -> Self {
+        Envelope {
             memory: vec![0.0; cap],
-            decay_rate: (1.0 / (- decay_time * sample_rate)).exp(),
+            attack_rate: time_constant(frame_rate, attack_time),
+            release_rate: time_constant(frame_rate, release_time),
+            detector,
             max
         }
     }
 }
 
-impl ProcessMut<f32, f32> for ExpDecay {
+impl ProcessMut<f32, f32> for Envelope {
     fn process(&mut self, sig: &[f32], output: &mut[f32], _: f32) {
         for (&i, (o, m)) in sig.iter().zip(output.iter_mut().zip(self.memory.iter_mut())) {
-            let mut tmp = self.decay_rate * *m;
-            tmp += i;
+            // RMS mode follows the power and takes the root on the way out.
+            let x = match self.detector {
+                Detector::Peak => i,
+                Detector::Rms => i * i
+            };
+
+            let rate = if x > *m { self.attack_rate } else { self.release_rate };
+            let mut tmp = x + rate * (*m - x);
             if tmp < 0.0000005 {tmp = 0.0}
-            tmp = f32::min(tmp, self.max);
-
             *m = tmp;
-            *o = tmp;
+
+            let level = match self.detector {
+                Detector::Peak => tmp,
+                Detector::Rms => tmp.sqrt()
+            };
+            *o = f32::min(level, self.max);
+        }
+    }
+}
+
+pub struct Pow {
+    exp: f32
+}
+
+impl Pow {
+    pub fn new(exp: f32) -> Self {
+        Pow {
+            exp
+        }
+    }
+}
+
+impl Process<f32, f32> for Pow {
+    fn process(&self, sig: &[f32], output: &mut[f32], _: f32) {
+        for (&i, o) in sig.iter().zip(output.iter_mut()) {
+            *o = i.powf(self.exp);
+        }
+    }
+}
+
+// Per bin linear gains, see `EqCurve::bin_gains`.
+pub struct Equalizer {
+    gains: Vec<f32>
+}
+
+impl Equalizer {
+    pub fn new(gains: Vec<f32>) -> Self {
+        Equalizer {
+            gains
+        }
+    }
+}
+
+impl Process<f32, f32> for Equalizer {
+    fn process(&self, sig: &[f32], output: &mut[f32], _: f32) {
+        for ((&i, o), &gain) in sig.iter().zip(output.iter_mut()).zip(self.gains.iter()) {
+            *o = i * gain;
+        }
+    }
+}
+
+// Zeroes anything below `threshold`.
+pub struct Floor {
+    threshold: f32
+}
+
+impl Floor {
+    pub fn new(threshold: f32) -> Self {
+        Floor {
+            threshold
+        }
+    }
+}
+
+impl Process<f32, f32> for Floor {
+    fn process(&self, sig: &[f32], output: &mut[f32], _: f32) {
+        for (&i, o) in sig.iter().zip(output.iter_mut()) {
+            *o = if i < self.threshold { 0.0 } else { i };
+        }
+    }
+}
+
+// Maps linear amplitude onto 0..1 by level in dB, `floor` dB and below is 0, `ceil` dB and above is 1.
+pub struct DbScale {
+    floor: f32,
+    ceil: f32
+}
+
+impl DbScale {
+    pub fn new(floor: f32, ceil: f32) -> Result<Self, String> {
+        if !(floor.is_finite() && ceil.is_finite()) || floor >= ceil {
+            return Err(format!("dB floor {} should be finite and below the ceiling {}", floor, ceil));
+        }
+
+        Ok(DbScale {
+            floor,
+            ceil
+        })
+    }
+}
+
+impl Process<f32, f32> for DbScale {
+    fn process(&self, sig: &[f32], output: &mut[f32], _: f32) {
+        for (&i, o) in sig.iter().zip(output.iter_mut()) {
+            let db = 20.0 * i.max(1e-10).log10();
+            *o = f32::min((db - self.floor) / (self.ceil - self.floor), 1.0).max(0.0);
+        }
+    }
+}
+
+// Average level over a frequency band. Each bin is weighted by how much of its width falls
+// inside [`lo`, `hi`) Hz, so the band means the same thing at any FFT size.
+pub struct BandPower {
+    start: usize,
+    weights: Vec<f32>
+}
+
+impl BandPower {
+    pub fn new(fft_size: usize, sample_rate: f32, lo: f32, hi: f32) -> Self {
+        let max_bin = (fft_size / 2 - 1) as f32;
+        let lo = f32::min(f_to_bin_f(fft_size, sample_rate, lo), max_bin);
+        let hi = f32::min(f_to_bin_f(fft_size, sample_rate, hi), max_bin + 0.5);
+
+        // Bin k covers [k - 0.5, k + 0.5).
+        let start = (lo + 0.5).floor() as usize;
+        let end = usize::max((hi + 0.5).ceil() as usize, start + 1);
+        let weights = (start..end).map(|k| {
+            let k = k as f32;
+            f32::max(f32::min(k + 0.5, hi) - f32::max(k - 0.5, lo), 0.0)
+        }).collect();
+
+        BandPower {
+            start,
+            weights
+        }
+    }
+
+    pub fn bins(&self) -> std::ops::Range<usize> {
+        self.start..self.start + self.weights.len()
+    }
+
+    pub fn power(&self, spectrum: &[f32]) -> f32 {
+        let total: f32 = self.weights.iter().sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+
+        spectrum[self.bins()].iter().zip(self.weights.iter()).map(|(x, w)| x * w).sum::<f32>() / total
+    }
+}
+
+// Gaussian smoothing across neighbouring elements, `sigma` is in elements. The kernel is
+// renormalized at the edges so the ends of the strip don't darken.
+pub struct GaussianBlur {
+    kernel: Vec<f32>
+}
+
+impl GaussianBlur {
+    // A `sigma` of 0 passes the signal through untouched.
+    pub fn new(sigma: f32) -> Result<Self, String> {
+        if !(sigma.is_finite() && sigma >= 0.0) {
+            return Err(format!("blur width {} must be zero or positive", sigma));
         }
+
+        let radius = (3.0 * sigma).ceil() as isize;
+        let kernel = (-radius..=radius)
+            .map(|x| if sigma > 0.0 { (-(x * x) as f32 / (2.0 * sigma * sigma)).exp() } else { 1.0 })
+            .collect();
+
+        Ok(GaussianBlur {
+            kernel
+        })
+    }
+}
+
+impl Process<f32, f32> for GaussianBlur {
+    fn process(&self, sig: &[f32], output: &mut[f32], _: f32) {
+        let radius = (self.kernel.len() / 2) as isize;
+        for (idx, o) in output.iter_mut().take(sig.len()).enumerate() {
+            let (mut acc, mut norm) = (0.0, 0.0);
+            for (k, &w) in self.kernel.iter().enumerate() {
+                let src = idx as isize + k as isize - radius;
+                if src >= 0 && (src as usize) < sig.len() {
+                    acc += sig[src as usize] * w;
+                    norm += w;
+                }
+            }
+            *o = acc / norm;
+        }
+    }
+}
+
+// Classic peak marker. Each value's peak is held for `hold_time` seconds,
+// then falls with constant acceleration `gravity` in full scales per second squared.
+pub struct PeakHold {
+    peaks: Vec<f32>,
+    hold: Vec<f32>,
+    velocity: Vec<f32>,
+    dt: f32,
+    hold_time: f32,
+    gravity: f32
+}
+
+impl PeakHold {
+    pub fn new(cap: usize, frame_rate: f32, hold_time: f32, gravity: f32) -> Self {
+        PeakHold {
+            peaks: vec![0.0; cap],
+            hold: vec![0.0; cap],
+            velocity: vec![0.0; cap],
+            dt: frame_rate.recip(),
+            hold_time,
+            gravity
+        }
+    }
+}
+
+impl ProcessMut<f32, f32> for PeakHold {
+    fn process(&mut self, sig: &[f32], output: &mut[f32], _: f32) {
+        let state = self.peaks.iter_mut().zip(self.hold.iter_mut().zip(self.velocity.iter_mut()));
+        for ((&i, o), (peak, (hold, velocity))) in sig.iter().zip(output.iter_mut()).zip(state) {
+            if i >= *peak {
+                *peak = i;
+                *hold = self.hold_time;
+                *velocity = 0.0;
+            } else if *hold > 0.0 {
+                *hold -= self.dt;
+            } else {
+                *velocity += self.gravity * self.dt;
+                *peak = f32::max(*peak - *velocity * self.dt, i);
+            }
+
+            *o = *peak;
+        }
+    }
+}
+
+// Peak level estimator for the AGCs. The level chases louder peaks with the rise time
+// and decays towards quieter ones with the fall time, both in seconds, so the AGC window
+// doesn't depend on the FFT size or overlap.
+#[derive(Debug, Clone, Copy)]
+pub struct LevelTracker {
+    level: f32,
+    rise_rate: f32,
+    fall_rate: f32
+}
+
+impl LevelTracker {
+    pub fn new(frame_rate: f32, rise_time: f32, fall_time: f32, initial: f32) -> Self {
+        LevelTracker {
+            level: initial,
+            rise_rate: time_constant(frame_rate, rise_time),
+            fall_rate: time_constant(frame_rate, fall_time)
+        }
+    }
+
+    pub fn update(&mut self, peak: f32) -> f32 {
+        let rate = if peak > self.level { self.rise_rate } else { self.fall_rate };
+        self.level = peak + rate * (self.level - peak);
+        self.level
     }
 }
 
 pub struct AGC {
-    memory: VecDeque<f32>,
+    tracker: LevelTracker,
     target: f32,
-    linear_equalizer: f32
+    max_gain: f32
 }
 
 impl AGC {
-    pub fn new(cap: usize, target: f32, linear_equalizer: f32) -> Self {
-        let mut memory = VecDeque::with_capacity(cap);
-        memory.resize(cap, target);
-
+    // `max_gain` caps the scaler so near silence isn't blown up to full brightness.
+    pub fn new(tracker: LevelTracker, target: f32, max_gain: f32) -> Self {
         AGC {
-            memory,
+            tracker,
             target,
-            linear_equalizer
+            max_gain
         }
     }
 }
 
 impl ProcessMut<f32, f32> for AGC {
     fn process(&mut self, sig: &[f32], output: &mut[f32], _: f32) {
-        let mut peak: f32 = 0.0;
-        let base = 12.0;
-        for (x, n) in sig.iter().enumerate() {
-
-            //let correction = (((x as f32) + 4.0)/base).min(1.0);
-            let correction = ((x as f32 / base) + self.linear_equalizer).min(1.0);
+        let peak = sig.iter().fold(0.0, |peak: f32, &i| peak.max(i));
 
-            let val = *n as f32 * correction;
-            peak = peak.max(val);
-        }
+        let level = self.tracker.update(peak);
+        let scaler = f32::min(self.target / level, self.max_gain);
 
-        if peak > 0.0 {
-            self.memory.pop_front();
-            self.memory.push_back(peak);
+        for (&i, o) in sig.iter().zip(output.iter_mut()) {
+            *o = f32::min(i * scaler, 1.0).max(0.0);
         }
+    }
+}
 
-        let running_avg : f32 = self.memory.iter().sum::<f32>() / (self.memory.len() as f32);
-        let scaler = self.target / running_avg;
+// Splits the spectrum into contiguous bands, each normalized by its own AGC so a loud
+// band can't pull the gain of the others down.
+pub struct MultiBandAGC {
+    bands: Vec<(usize, AGC)>
+}
 
-        for (x, (&i, o)) in sig.iter().zip(output.iter_mut()).enumerate() {
-            let correction = 1.0 - ((x as f32 / base) + self.linear_equalizer).min(1.0);
+impl MultiBandAGC {
+    // `bands` holds the exclusive upper bin of each band along with its AGC, in ascending order.
+    // The first band starts at bin 0 and the last one is stretched over the rest of the spectrum.
+    pub fn new(bands: Vec<(usize, AGC)>) -> Self {
+        MultiBandAGC {
+            bands
+        }
+    }
+}
 
-            *o = f32::min((i * scaler) , 1.0).max(0.0);
+impl ProcessMut<f32, f32> for MultiBandAGC {
+    fn process(&mut self, sig: &[f32], output: &mut[f32], p: f32) {
+        let last = self.bands.len() - 1;
+        let mut start = 0;
+        for (idx, (end, agc)) in self.bands.iter_mut().enumerate() {
+            let end = if idx == last { sig.len() } else { usize::min(*end, sig.len()) };
+            let start_bin = usize::min(start, end);
+            agc.process(&sig[start_bin..end], &mut output[start_bin..end], p);
+            start = end;
         }
     }
 }
 
 pub struct TimeAGC {
-    memory: VecDeque<f32>,
+    tracker: LevelTracker,
     target: f32,
+    max_gain: f32,
 }
 
 impl TimeAGC {
-    pub fn new(cap: usize, target: f32) -> Self {
-        let mut memory = VecDeque::with_capacity(cap);
-        memory.resize(cap, target);
-
+    pub fn new(tracker: LevelTracker, target: f32, max_gain: f32) -> Self {
         TimeAGC {
-            memory,
+            tracker,
             target,
+            max_gain,
         }
     }
 }
@@ -197,16 +669,106 @@ impl ProcessMut<f32, f32> for TimeAGC {
 
         //println!("{}", peak);
 
-        if peak > 0.0 {
-            self.memory.pop_front();
-            self.memory.push_back(peak);
+        let level = self.tracker.update(peak);
+        let scaler = f32::min(self.target / level, self.max_gain);
+
+        for (&i, o) in sig.iter().zip(output.iter_mut()) {
+            *o = f32::min(i * scaler, 1.0).max(-1.0);
         }
+    }
+}
 
-        let running_avg : f32 = self.memory.iter().sum::<f32>() / (self.memory.len() as f32);
-        let scaler = self.target / running_avg;
+// One pole DC blocking high-pass, y[n] = x[n] - x[n-1] + r * y[n-1].
+// A cutoff of 0 Hz gives r = 1, which passes the signal through unchanged. Negative cutoffs
+// would put r above 1 and make the filter blow up, so the cutoff must be in 0..nyquist.
+#[derive(Clone)]
+pub struct DcBlock {
+    r: f32,
+    x1: f32,
+    y1: f32
+}
 
+impl DcBlock {
+    pub fn new(sample_rate: f32, cutoff: f32) -> Result<Self, String> {
+        if !(cutoff >= 0.0 && cutoff < sample_rate / 2.0) {
+            return Err(format!("high-pass cutoff {} Hz should be at least 0 and below nyquist at {}", cutoff, sample_rate / 2.0));
+        }
+
+        Ok(DcBlock {
+            r: (-2.0 * std::f32::consts::PI * cutoff / sample_rate).exp(),
+            x1: 0.0,
+            y1: 0.0
+        })
+    }
+
+    pub fn filter(&mut self, x: f32) -> f32 {
+        let y = x - self.x1 + self.r * self.y1;
+        self.x1 = x;
+        self.y1 = y;
+        y
+    }
+}
+
+impl ProcessMut<f32, f32> for DcBlock {
+    fn process(&mut self, sig: &[f32], output: &mut[f32], _: f32) {
         for (&i, o) in sig.iter().zip(output.iter_mut()) {
-            *o = f32::min(i * scaler, 1.0).max(-1.0);
+            *o = self.filter(i);
+        }
+    }
+}
+
+// Time domain noise gate. Opens when the frame's RMS level rises above `threshold` dBFS,
+// and closes once it has been below `threshold - hysteresis` for `hold_time` seconds.
+// While closed the output is silent.
+pub struct NoiseGate {
+    open: bool,
+    held: f32,
+    dt: f32,
+    threshold: f32,
+    hysteresis: f32,
+    hold_time: f32
+}
+
+impl NoiseGate {
+    pub fn new(frame_rate: f32, threshold: f32, hysteresis: f32, hold_time: f32) -> Self {
+        NoiseGate {
+            open: false,
+            held: 0.0,
+            dt: frame_rate.recip(),
+            threshold,
+            hysteresis,
+            hold_time
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+impl ProcessMut<f32, f32> for NoiseGate {
+    fn process(&mut self, sig: &[f32], output: &mut[f32], _: f32) {
+        let rms = (sig.iter().map(|x| x * x).sum::<f32>() / sig.len() as f32).sqrt();
+        let level = 20.0 * rms.max(1e-10).log10();
+
+        if level > self.threshold {
+            self.open = true;
+        }
+
+        // Only continuous quiet counts towards the hold, so separate short dips don't add up.
+        if level >= self.threshold - self.hysteresis {
+            self.held = 0.0;
+        } else if self.open {
+            self.held += self.dt;
+            if self.held >= self.hold_time {
+                self.open = false;
+            }
+        }
+
+        if self.open {
+            output[..sig.len()].copy_from_slice(sig);
+        } else {
+            output.iter_mut().for_each(|o| *o = 0.0);
         }
     }
 }
@@ -220,10 +782,79 @@ impl<T: Copy> Process<T,T> for PassThrough {
     }
 }
 
+impl<T, U, P: Process<T, U> + ?Sized> Process<T, U> for Box<P> {
+    fn process(&self, sig: &[T], output: &mut [U], p: f32)
+    {
+        (**self).process(sig, output, p);
+    }
+}
+
 impl<T,U,P> ProcessMut<T,U> for P 
 where P: Process<T,U> {
     fn process(&mut self, sig: &[T], output: &mut [U], p: f32)
     {
         Process::process(self, sig, output, p);
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEDS: usize = 60;
+
+    #[test]
+    fn page_log_small_fft_stays_in_range() {
+        for &fft_size in [16, 32, 64, 128].iter() {
+            let map = PageLog::new(fft_size, 44100.0, 20.0, 20000.0, LEDS).unwrap();
+            let fft_nyquist = fft_size / 2;
+            for (lo, hi) in map.bin_table() {
+                assert!(lo >= 0.0 && hi <= (fft_nyquist - 1) as f32, "{} in [{}, {}]", fft_size, lo, hi);
+            }
+
+            // Every LED reads from within the spectrum.
+            let sig = vec![0.5; fft_nyquist];
+            let mut out = vec![0.0; LEDS];
+            Process::process(&map, &sig, &mut out, 1.0);
+            assert!(out.iter().all(|&o| (o - 0.5).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn page_log_edges_are_monotonic() {
+        for &fft_size in [64, 256, 1024, 4096].iter() {
+            for &(min, max) in [(0.0, 8000.0), (20.0, 8000.0), (100.0, 22050.0)].iter() {
+                let table = PageLog::new(fft_size, 44100.0, min, max, LEDS).unwrap().bin_table();
+                assert_eq!(table.len(), LEDS);
+                for (led, &(lo, hi)) in table.iter().enumerate() {
+                    assert!(lo <= hi, "{} [{}, {}] LED {}", fft_size, min, max, led);
+                }
+                for (led, w) in table.windows(2).enumerate() {
+                    assert_eq!(w[0].1, w[1].0, "{} [{}, {}] LED {}", fft_size, min, max, led);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn page_log_shared_bins_are_interpolated() {
+        let fft_size = 64;
+        let map = PageLog::new(fft_size, 44100.0, 20.0, 20000.0, LEDS).unwrap();
+        let sig: Vec<f32> = (0..fft_size / 2).map(|bin| bin as f32 / fft_size as f32).collect();
+        let mut out = vec![0.0; LEDS];
+        Process::process(&map, &sig, &mut out, 1.0);
+
+        // With more LEDs than bins some must share one, and on a rising spectrum they should
+        // still rise rather than repeat the bin's value.
+        let table = map.bin_table();
+        let shared = |&(lo, hi): &(f32, f32)| lo.round() == hi.round();
+        let mut pairs = 0;
+        for (led, w) in table.windows(2).enumerate() {
+            if shared(&w[0]) && shared(&w[1]) {
+                assert!(out[led + 1] > out[led], "LED {}: {} then {}", led, out[led], out[led + 1]);
+                pairs += 1;
+            }
+        }
+        assert!(pairs > 0);
+    }
 }
\ No newline at end of file
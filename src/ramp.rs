@@ -0,0 +1,129 @@
+use super::color::{self, RGB, RGBf};
+
+// How neighbouring colors of an `IntensityRamp` combine.
+#[derive(Debug, Clone, Copy)]
+pub enum RampBlend {
+    // Each color fills in over its own stretch of the ramp and stays lit on top of the ones
+    // below it, so loud signals overflow into the next channel.
+    Stack,
+    // Crossfade from each color to the next, starting from black.
+    Fade,
+}
+
+impl std::str::FromStr for RampBlend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stack" => Ok(RampBlend::Stack),
+            "fade" => Ok(RampBlend::Fade),
+            _ => Err(format!("unknown ramp blend `{}`, expected one of: stack, fade", s)),
+        }
+    }
+}
+
+// Maps an intensity in 0..1 straight to a color. Each stop is the intensity at which its
+// color is fully in, the stretch it ramps over starts at the previous stop.
+#[derive(Debug, Clone)]
+pub struct IntensityRamp {
+    stops: Vec<(f32, RGB)>,
+    blend: RampBlend,
+}
+
+impl IntensityRamp {
+    pub fn new(stops: &[(f32, RGB)]) -> Self {
+        let mut stops = stops.to_vec();
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        IntensityRamp {
+            stops,
+            blend: RampBlend::Stack,
+        }
+    }
+
+    pub fn with_blend(self, blend: RampBlend) -> Self {
+        IntensityRamp { blend, ..self }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        let stops: &[u32] = match name {
+            // Blue, overflowing into green.
+            "hdr" => &[0x0000FF, 0x00FF00],
+            // Blue, overflowing into green and then red.
+            "shdr" => &[0x0000FF, 0x00FF00, 0xFF0000],
+            _ => return None,
+        };
+
+        let stops: Vec<(f32, RGB)> = stops.iter()
+            .enumerate()
+            .map(|(idx, &hex)| ((idx + 1) as f32 / stops.len() as f32, RGB::from_u32(hex)))
+            .collect();
+        Some(IntensityRamp::new(&stops))
+    }
+
+    pub fn at(&self, intensity: f32) -> RGBf {
+        let mut out = RGBf::default();
+        let mut start = 0.0;
+        let mut below = RGBf::default();
+        for &(end, color) in self.stops.iter() {
+            let color = RGBf::from(color);
+            let fill = if end > start {
+                f32::min((intensity - start) / (end - start), 1.0).max(0.0)
+            } else if intensity >= end {
+                1.0
+            } else {
+                0.0
+            };
+
+            match self.blend {
+                RampBlend::Stack => {
+                    out.r = f32::min(out.r + fill * color.r, 255.0);
+                    out.g = f32::min(out.g + fill * color.g, 255.0);
+                    out.b = f32::min(out.b + fill * color.b, 255.0);
+                }
+                RampBlend::Fade if fill > 0.0 => {
+                    out = RGBf {
+                        r: below.r + (color.r - below.r) * fill,
+                        g: below.g + (color.g - below.g) * fill,
+                        b: below.b + (color.b - below.b) * fill,
+                    };
+                }
+                RampBlend::Fade => (),
+            }
+
+            start = end;
+            below = color;
+        }
+
+        out
+    }
+}
+
+// Either a preset (hdr, shdr), or comma separated stops. Stops are `end:color`, or a bare
+// color to space them evenly.
+impl std::str::FromStr for IntensityRamp {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(ramp) = IntensityRamp::preset(s) {
+            return Ok(ramp);
+        }
+
+        let fields = color::split_list(s);
+        let parse_color = |c: &str| c.parse::<RGB>().map_err(|e| format!("bad ramp color: {}", e));
+
+        let stops = fields.iter().enumerate().map(|(idx, field)| {
+            match field.find(':') {
+                Some(split) => {
+                    let end: f32 = field[..split].parse().map_err(|e| format!("bad ramp threshold `{}`: {}", &field[..split], e))?;
+                    if !(0.0..=1.0).contains(&end) {
+                        return Err(format!("ramp threshold `{}` should be between 0 and 1", &field[..split]));
+                    }
+                    Ok((end, parse_color(&field[split + 1..])?))
+                }
+                None => Ok(((idx + 1) as f32 / fields.len() as f32, parse_color(field)?)),
+            }
+        }).collect::<Result<Vec<_>, String>>()
+            .map_err(|e| format!("{} (ramp `{}` is not a preset: hdr, shdr)", e, s))?;
+
+        Ok(IntensityRamp::new(&stops))
+    }
+}